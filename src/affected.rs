@@ -0,0 +1,212 @@
+use std::{path::Path, process::Command};
+
+use color_eyre::eyre::{bail, Context, Result};
+use log::{debug, trace};
+
+use crate::{error::BraiseError, file::BraiseFile, workspace::CargoPackage};
+
+/// The files which affect every package when they change
+const WORKSPACE_FILES: [&str; 2] = ["Cargo.toml", "Cargo.lock"];
+
+/// Run git, returning the lines it printed
+fn git(args: &[&str]) -> Result<Vec<String>> {
+    debug!("Running git {}", args.join(" "));
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Couldn't run git")?;
+    if !output.status.success() {
+        bail!(BraiseError::Git(
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// The ref `--affected` compares against when none is given: the default branch of `origin`,
+/// or `main`
+pub fn default_base() -> String {
+    git(&["rev-parse", "--abbrev-ref", "origin/HEAD"])
+        .ok()
+        .and_then(|lines| lines.into_iter().next())
+        .filter(|base| !base.is_empty())
+        .unwrap_or("main".to_string())
+}
+
+/// The files changed since `base` diverged from `HEAD`, committed or not, relative to `dir`
+pub fn changed_files(dir: &Path, base: &str) -> Result<Vec<String>> {
+    trace!("changed_files: entering");
+    let dir = dir.to_string_lossy();
+    let merge_base = git(&["-C", &dir, "merge-base", base, "HEAD"])?
+        .into_iter()
+        .next()
+        .unwrap_or(base.to_string());
+    let mut files = git(&["-C", &dir, "diff", "--name-only", "--relative", &merge_base])?;
+    files.extend(git(&[
+        "-C",
+        &dir,
+        "ls-files",
+        "--others",
+        "--exclude-standard",
+    ])?);
+    debug!("Changed files: {:#?}", files);
+    trace!("changed_files: exiting");
+    Ok(files)
+}
+
+/// The packages affected by changes to `files`: those containing them, and every package
+/// depending on an affected one
+pub fn affected_packages<'a>(
+    packages: &'a [CargoPackage],
+    files: &[String],
+) -> Vec<&'a CargoPackage> {
+    trace!("affected_packages: entering");
+    let mut affected: Vec<&str> = vec![];
+    for file in files {
+        if WORKSPACE_FILES.contains(&file.as_str()) {
+            debug!("{} changed, every package is affected", file);
+            trace!("affected_packages: exiting");
+            return packages.iter().collect();
+        }
+        // Packages can be nested, so the file belongs to the deepest one containing it
+        let owner = packages
+            .iter()
+            .filter(|package| Path::new(file).starts_with(&package.dir))
+            .max_by_key(|package| package.dir.components().count());
+        if let Some(owner) = owner {
+            if !affected.contains(&owner.name.as_str()) {
+                affected.push(&owner.name);
+            }
+        }
+    }
+    let mut queue = affected.clone();
+    while let Some(name) = queue.pop() {
+        for dependent in packages
+            .iter()
+            .filter(|package| package.dependencies.iter().any(|dep| dep == name))
+        {
+            if !affected.contains(&dependent.name.as_str()) {
+                debug!("{} is affected through {}", dependent.name, name);
+                affected.push(&dependent.name);
+                queue.push(&dependent.name);
+            }
+        }
+    }
+    trace!("affected_packages: exiting");
+    packages
+        .iter()
+        .filter(|package| affected.contains(&package.name.as_str()))
+        .collect()
+}
+
+/// Replace every task having a variant per package (like `test`, with `test:core`, `test:cli`,
+/// ...) by the variants of the affected packages. Other tasks, including `foreach` ones, are
+/// kept as is
+pub fn affected_tasks(
+    file: &BraiseFile,
+    tasks: Vec<String>,
+    packages: &[CargoPackage],
+    affected: &[&CargoPackage],
+) -> Vec<String> {
+    trace!("affected_tasks: entering");
+    let mut expanded = vec![];
+    for task in tasks {
+        let name = file.resolve(&task).unwrap_or(&task);
+        // Tasks run for each package are restricted to the affected ones by `BraiseFile::packages`
+        if file
+            .describe_task(name)
+            .is_some_and(|task| task.foreach.is_some())
+        {
+            expanded.push(task);
+            continue;
+        }
+        let variant = |package: &CargoPackage| format!("{}:{}", name, package.name);
+        if !packages
+            .iter()
+            .any(|package| file.resolve(&variant(package)).is_some())
+        {
+            expanded.push(task);
+            continue;
+        }
+        expanded.extend(
+            affected
+                .iter()
+                .map(|package| variant(package))
+                .filter(|variant| file.resolve(variant).is_some()),
+        );
+    }
+    debug!("Affected tasks: {:?}", expanded);
+    trace!("affected_tasks: exiting");
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::affected_packages;
+    use crate::workspace::CargoPackage;
+
+    fn package(name: &str, dir: &str, dependencies: &[&str]) -> CargoPackage {
+        CargoPackage {
+            name: name.to_string(),
+            dir: PathBuf::from(dir),
+            bins: vec![],
+            examples: vec![],
+            benches: vec![],
+            dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+        }
+    }
+
+    fn affected(packages: &[CargoPackage], files: &[&str]) -> Vec<String> {
+        let files = files
+            .iter()
+            .map(|file| file.to_string())
+            .collect::<Vec<_>>();
+        affected_packages(packages, &files)
+            .into_iter()
+            .map(|package| package.name.to_string())
+            .collect()
+    }
+
+    fn workspace() -> Vec<CargoPackage> {
+        vec![
+            package("app", "", &["cli"]),
+            package("core", "crates/core", &[]),
+            package("cli", "crates/cli", &["core"]),
+            package("macros", "crates/core/macros", &[]),
+            package("docs", "docs", &[]),
+        ]
+    }
+
+    #[test]
+    fn dependents_are_affected_transitively() {
+        assert_eq!(
+            affected(&workspace(), &["crates/core/src/lib.rs"]),
+            ["app", "core", "cli"]
+        );
+        assert_eq!(affected(&workspace(), &["docs/README.md"]), ["docs"]);
+    }
+
+    #[test]
+    fn files_belong_to_the_deepest_package() {
+        assert_eq!(
+            affected(&workspace(), &["crates/core/macros/src/lib.rs"]),
+            ["macros"]
+        );
+        assert_eq!(affected(&workspace(), &["src/main.rs"]), ["app"]);
+    }
+
+    #[test]
+    fn workspace_files_affect_every_package() {
+        assert_eq!(
+            affected(&workspace(), &["docs/README.md", "Cargo.lock"]),
+            ["app", "core", "cli", "macros", "docs"]
+        );
+        assert!(affected(&workspace(), &[]).is_empty());
+    }
+}
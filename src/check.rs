@@ -0,0 +1,152 @@
+use std::{ops::Range, path::Path};
+
+use color_eyre::{
+    eyre::{bail, Result},
+    owo_colors::OwoColorize,
+};
+use log::{debug, trace};
+use toml_edit::{ImDocument, Item, Table, Value};
+
+use crate::{
+    error::BraiseError,
+    file::{BraiseFile, FileFormat},
+};
+
+/// A problem found while reading a Braisefile
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The keys leading to the problematic value, e.g. `["build", "dependencies", "0"]`
+    pub keys: Vec<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(keys: Vec<String>, message: impl Into<String>) -> Self {
+        Self {
+            keys,
+            message: message.into(),
+        }
+    }
+}
+
+/// A position in a Braisefile, 1-based like most editors and compilers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn from_offset(source: &str, offset: usize) -> Self {
+        let before = &source[..offset.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rfind('\n')
+            .map_or(before, |newline| &before[newline + 1..])
+            .chars()
+            .count()
+            + 1;
+        Self { line, column }
+    }
+}
+
+enum Node<'a> {
+    Item(&'a Item),
+    Table(&'a Table),
+    Value(&'a Value),
+}
+
+/// Find the byte range of the deepest key of `keys` present in a TOML document
+pub fn locate(document: &ImDocument<&str>, keys: &[String]) -> Option<Range<usize>> {
+    let mut node = Node::Table(document.as_table());
+    let mut span = None;
+    for key in keys {
+        let index = key.parse::<usize>().ok();
+        let (next, next_span) = match node {
+            Node::Item(Item::Table(table)) | Node::Table(table) => {
+                let Some((key, item)) = table.get_key_value(key) else {
+                    break;
+                };
+                (Node::Item(item), key.span().or(item.span()))
+            }
+            Node::Item(Item::ArrayOfTables(array)) => {
+                let Some(table) = index.and_then(|index| array.get(index)) else {
+                    break;
+                };
+                (Node::Table(table), table.span())
+            }
+            Node::Item(Item::Value(Value::InlineTable(table)))
+            | Node::Value(Value::InlineTable(table)) => {
+                let Some((key, item)) = table.get_key_value(key) else {
+                    break;
+                };
+                (Node::Item(item), key.span().or(item.span()))
+            }
+            Node::Item(Item::Value(Value::Array(array))) | Node::Value(Value::Array(array)) => {
+                let Some(value) = index.and_then(|index| array.get(index)) else {
+                    break;
+                };
+                (Node::Value(value), value.span())
+            }
+            _ => break,
+        };
+        node = next;
+        span = next_span.or(span);
+    }
+    span
+}
+
+/// Format diagnostics as `path:line:column: message`, falling back to the keys when the
+/// location can't be found (always the case for YAML and JSON files)
+pub fn format_diagnostics(path: &str, source: &str, diagnostics: &[Diagnostic]) -> String {
+    let document = (FileFormat::from_path(path) == FileFormat::Toml)
+        .then(|| ImDocument::parse(source).ok())
+        .flatten();
+    let mut located = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let location = document
+                .as_ref()
+                .and_then(|document| locate(document, &diagnostic.keys))
+                .map(|span| Location::from_offset(source, span.start));
+            (location, diagnostic)
+        })
+        .collect::<Vec<_>>();
+    located.sort_by_key(|(location, _)| location.map(|l| (l.line, l.column)));
+    located
+        .into_iter()
+        .map(|(location, diagnostic)| match location {
+            Some(Location { line, column }) => {
+                format!("{}:{}:{}: {}", path, line, column, diagnostic.message)
+            }
+            None => format!(
+                "{}: {}: {}",
+                path,
+                diagnostic.keys.join("."),
+                diagnostic.message
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Report every problem of a Braisefile, failing if there is any
+pub fn check_file(path: &str) -> Result<()> {
+    trace!("check_file: entering");
+    let source = std::fs::read_to_string(path)?;
+    let value = FileFormat::from_path(path).parse(path, &source)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let file = BraiseFile::from_value(value, dir)?;
+    debug!("Diagnostics: {:#?}", file.diagnostics);
+    if !file.diagnostics.is_empty() {
+        println!("{}", format_diagnostics(path, &source, &file.diagnostics));
+        trace!("check_file: exiting with error");
+        bail!(BraiseError::InvalidFile(
+            path.to_string(),
+            file.diagnostics.len()
+        ));
+    }
+    println!("No problems found in {}", path.bold());
+    trace!("check_file: exiting");
+    Ok(())
+}
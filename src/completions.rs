@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use clap::{Arg, Command};
+use clap_complete::env::Shells;
+use color_eyre::eyre::{eyre, Result};
+use log::{debug, trace};
+
+use crate::{
+    constants::FILE_ENV_VAR,
+    file::{find_file_in, BraiseFile},
+};
+
+/// The Braisefile the command line being completed runs, found like `main` does: `-f` (or
+/// `BRAISE_FILE`) relative to `-C`, otherwise the Braisefile of `-C` or the current directory
+fn completed_file(words: &[String]) -> Option<String> {
+    let (mut dir, mut file) = (None, None);
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        // `--dir=DIR` and `-CDIR` hold their value, `--dir DIR` and `-C DIR` are followed by it
+        let (flag, value) = if let Some((flag, value)) = word
+            .split_once('=')
+            .filter(|(flag, _)| flag.starts_with("--"))
+        {
+            (flag, Some(value.to_string()))
+        } else if word.starts_with('-') && !word.starts_with("--") && word.is_char_boundary(2) {
+            let (flag, value) = word.split_at(2);
+            (flag, (!value.is_empty()).then(|| value.to_string()))
+        } else {
+            (word.as_str(), None)
+        };
+        let slot = match flag {
+            "-C" | "--dir" => &mut dir,
+            "-f" | "--file" => &mut file,
+            // The options of the tasks come after their name
+            _ if !word.starts_with('-') => break,
+            _ => continue,
+        };
+        *slot = value.or_else(|| words.next().cloned());
+    }
+    let dir = PathBuf::from(dir.unwrap_or_default());
+    match file.or_else(|| std::env::var(FILE_ENV_VAR).ok()) {
+        Some(file) => Some(dir.join(file).display().to_string()),
+        None => find_file_in(&dir).ok(),
+    }
+}
+
+/// Add a subcommand for every task of the Braisefile of the command line being completed, so
+/// that task names, aliases and the choices of declared arguments can be completed.
+///
+/// This is only used when completing, as the tasks are otherwise parsed as external subcommands.
+pub fn with_task_subcommands(mut cmd: Command) -> Command {
+    trace!("with_task_subcommands: entering");
+    // The shell calls us with the words being completed after `--`, our own name first
+    let words = std::env::args()
+        .skip_while(|arg| arg != "--")
+        .skip(2)
+        .collect::<Vec<_>>();
+    // Problems of the file are reported when running it, not in the middle of the command line
+    let Some(file) = completed_file(&words).and_then(|path| BraiseFile::read(&path).ok()) else {
+        debug!("No Braisefile to complete tasks from");
+        return cmd;
+    };
+    for (name, _) in file.all_tasks() {
+        let Some(task) = file.describe_task(name) else {
+            continue;
+        };
+        let mut subcommand = Command::new(name.to_string())
+            .hide(task.is_hidden(name))
+            .visible_aliases(task.aliases.iter().flatten().map(|alias| alias.to_string()));
+        if let Some(ref description) = task.description {
+            subcommand = subcommand.about(description.to_string());
+        }
+        for arg in task.args.iter().flatten() {
+            let mut completed = Arg::new(arg.name.to_string());
+            if let Some(ref description) = arg.description {
+                completed = completed.help(description.to_string());
+            }
+            if let Some(ref choices) = arg.choices {
+                completed = completed.value_parser(choices.clone());
+            }
+            subcommand = subcommand.arg(completed);
+        }
+        cmd = cmd.subcommand(subcommand);
+    }
+    trace!("with_task_subcommands: exiting");
+    cmd.disable_help_subcommand(true)
+}
+
+/// Print the script registering the completions of the current binary for `shell`
+pub fn print_completions(shell: &str) -> Result<()> {
+    trace!("print_completions: entering");
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell)
+        .ok_or(eyre!("Unsupported shell: {}", shell))?;
+    // Complete for the name we were invoked with, so that both `braise` and `br` work
+    let bin = std::env::args_os()
+        .next()
+        .as_ref()
+        .and_then(|arg| std::path::Path::new(arg).file_name().map(|n| n.to_owned()))
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or(env!("CARGO_PKG_NAME").to_string());
+    completer.write_registration(
+        "COMPLETE",
+        env!("CARGO_PKG_NAME"),
+        &bin,
+        &bin,
+        &mut std::io::stdout(),
+    )?;
+    trace!("print_completions: exiting");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::completed_file;
+
+    fn file(words: &[&str]) -> Option<String> {
+        let words = words
+            .iter()
+            .map(|word| word.to_string())
+            .collect::<Vec<_>>();
+        completed_file(&words)
+    }
+
+    #[test]
+    fn completed_file_follows_dir_and_file() {
+        let dir = std::env::temp_dir().join(format!("braise-completions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("braise.toml"), "").unwrap();
+        let dir = dir.display().to_string();
+        let found = format!("{}/braise.toml", dir);
+        assert_eq!(file(&["-C", &dir, "bu"]), Some(found.clone()));
+        assert_eq!(file(&[&format!("--dir={}", dir)]), Some(found.clone()));
+        assert_eq!(file(&["-q", &format!("-C{}", dir)]), Some(found));
+        assert_eq!(
+            file(&["-C", &dir, "--file", "tasks.yaml"]),
+            Some(format!("{}/tasks.yaml", dir))
+        );
+        // Only the options before the task are ours
+        assert_eq!(
+            file(&["-f", "a.toml", "build", "-f", "b.toml"]),
+            Some("a.toml".to_string())
+        );
+    }
+}
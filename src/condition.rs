@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    iter::Peekable,
+    path::Path,
+    process::{Command, Stdio},
+    str::Chars,
+};
+
+use log::debug;
+
+use crate::utils::split_shell;
+
+/// A value compared by a condition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    /// A quoted string, e.g. `'true'`
+    Literal(String),
+    /// An environment variable, e.g. `env.CI`
+    Env(String),
+    /// The current OS, e.g. `linux`
+    Os,
+    /// The current architecture, e.g. `x86_64`
+    Arch,
+    /// The current OS family, `unix` or `windows`
+    Family,
+}
+
+/// A parsed `when` expression, e.g. `env.CI == 'true' && exists('Dockerfile')`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Bool(bool),
+    /// Whether the operand is set and not empty
+    Truthy(Operand),
+    Equal(Operand, Operand),
+    NotEqual(Operand, Operand),
+    /// Whether the path exists, e.g. `exists('Cargo.lock')`
+    Exists(String),
+    /// Whether the command succeeds, e.g. `success('docker info')`
+    Success(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    String(String),
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '\'' | '"' => Token::String(string(&mut chars, c)?),
+            '=' | '&' | '|' if chars.peek() == Some(&c) => {
+                chars.next();
+                match c {
+                    '=' => Token::Equal,
+                    '&' => Token::And,
+                    _ => Token::Or,
+                }
+            }
+            '!' if chars.peek() == Some(&'=') => {
+                chars.next();
+                Token::NotEqual
+            }
+            '!' => Token::Not,
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                Token::Ident(ident)
+            }
+            c => return Err(format!("unexpected {}", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn string(chars: &mut Peekable<Chars>, quote: char) -> Result<String, String> {
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            Some(c) if c == quote => return Ok(string),
+            Some('\\') => string.extend(chars.next()),
+            Some(c) => string.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("expected {}", what)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> Result<Condition, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Condition, String> {
+        match self.peek() {
+            Some(Token::Open) => {
+                self.next();
+                let condition = self.or()?;
+                self.expect(Token::Close, ")")?;
+                return Ok(condition);
+            }
+            Some(Token::Ident(ident)) if ident == "true" || ident == "false" => {
+                let value = ident == "true";
+                self.next();
+                return Ok(Condition::Bool(value));
+            }
+            Some(Token::Ident(ident)) if ident == "exists" || ident == "success" => {
+                let function = ident.to_string();
+                self.next();
+                self.expect(Token::Open, &format!("( after {}", function))?;
+                let Some(Token::String(argument)) = self.next() else {
+                    return Err(format!("expected a string argument to {}", function));
+                };
+                self.expect(Token::Close, ")")?;
+                return Ok(if function == "exists" {
+                    Condition::Exists(argument)
+                } else {
+                    Condition::Success(argument)
+                });
+            }
+            _ => {}
+        }
+        let left = self.operand()?;
+        match self.peek() {
+            Some(Token::Equal) => {
+                self.next();
+                Ok(Condition::Equal(left, self.operand()?))
+            }
+            Some(Token::NotEqual) => {
+                self.next();
+                Ok(Condition::NotEqual(left, self.operand()?))
+            }
+            _ => Ok(Condition::Truthy(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.next() {
+            Some(Token::String(string)) => Ok(Operand::Literal(string)),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "os" => Ok(Operand::Os),
+                "arch" => Ok(Operand::Arch),
+                "family" => Ok(Operand::Family),
+                _ => match ident.strip_prefix("env.") {
+                    Some(var) if !var.is_empty() && !var.contains('.') => {
+                        Ok(Operand::Env(var.to_string()))
+                    }
+                    _ => Err(format!("unknown value {}", ident)),
+                },
+            },
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of the condition".to_string()),
+        }
+    }
+}
+
+impl Operand {
+    fn value(&self, env_vars: &HashMap<String, String>) -> Option<String> {
+        match self {
+            Self::Literal(value) => Some(value.to_string()),
+            Self::Env(var) => env_vars.get(var).cloned(),
+            Self::Os => Some(std::env::consts::OS.to_string()),
+            Self::Arch => Some(std::env::consts::ARCH.to_string()),
+            Self::Family => Some(std::env::consts::FAMILY.to_string()),
+        }
+    }
+}
+
+impl Condition {
+    /// Parse a `when` expression, failing with the reason it is invalid
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let condition = parser.or()?;
+        match parser.next() {
+            None => Ok(condition),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    /// Whether the condition holds in the environment of the task, running the commands of
+    /// `success(...)` with `shell`. Only the operands deciding the result are evaluated
+    pub fn evaluate(&self, shell: &str, env_vars: &HashMap<String, String>) -> bool {
+        match self {
+            Self::Bool(value) => *value,
+            Self::Truthy(operand) => operand
+                .value(env_vars)
+                .is_some_and(|value| !value.is_empty()),
+            // An unset variable is only equal to the empty string
+            Self::Equal(left, right) => {
+                left.value(env_vars).unwrap_or_default()
+                    == right.value(env_vars).unwrap_or_default()
+            }
+            Self::NotEqual(left, right) => {
+                left.value(env_vars).unwrap_or_default()
+                    != right.value(env_vars).unwrap_or_default()
+            }
+            Self::Exists(path) => Path::new(path).exists(),
+            Self::Success(command) => {
+                let (program, args) = split_shell(shell);
+                debug!("Checking whether {} succeeds", command);
+                Command::new(program)
+                    .args(args)
+                    .arg(command)
+                    .envs(env_vars)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .is_ok_and(|status| status.success())
+            }
+            Self::Not(condition) => !condition.evaluate(shell, env_vars),
+            Self::And(left, right) => {
+                left.evaluate(shell, env_vars) && right.evaluate(shell, env_vars)
+            }
+            Self::Or(left, right) => {
+                left.evaluate(shell, env_vars) || right.evaluate(shell, env_vars)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Condition, Operand};
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn holds(input: &str, env_vars: &HashMap<String, String>) -> bool {
+        Condition::parse(input).unwrap().evaluate("sh -c", env_vars)
+    }
+
+    #[test]
+    fn parse_gives_precedence_to_not_then_and() {
+        assert_eq!(
+            Condition::parse("!env.A || env.B == 'x' && os != \"windows\"").unwrap(),
+            Condition::Or(
+                Box::new(Condition::Not(Box::new(Condition::Truthy(Operand::Env(
+                    "A".to_string()
+                ))))),
+                Box::new(Condition::And(
+                    Box::new(Condition::Equal(
+                        Operand::Env("B".to_string()),
+                        Operand::Literal("x".to_string())
+                    )),
+                    Box::new(Condition::NotEqual(
+                        Operand::Os,
+                        Operand::Literal("windows".to_string())
+                    )),
+                )),
+            )
+        );
+        assert_eq!(
+            Condition::parse("(true || false) && exists('a b')").unwrap(),
+            Condition::And(
+                Box::new(Condition::Or(
+                    Box::new(Condition::Bool(true)),
+                    Box::new(Condition::Bool(false))
+                )),
+                Box::new(Condition::Exists("a b".to_string())),
+            )
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_conditions() {
+        for input in [
+            "",
+            "env.",
+            "foo == 'x'",
+            "env.CI ==",
+            "exists(Cargo.toml)",
+            "(true",
+            "true false",
+            "'unterminated",
+            "env.CI = 'true'",
+        ] {
+            assert!(Condition::parse(input).is_err(), "{} parsed", input);
+        }
+    }
+
+    #[test]
+    fn evaluate_reads_the_given_environment() {
+        let env_vars = env(&[("CI", "true"), ("EMPTY", "")]);
+        assert!(holds("env.CI == 'true'", &env_vars));
+        assert!(holds("env.CI", &env_vars));
+        assert!(!holds("env.EMPTY", &env_vars));
+        // An unset variable is only equal to the empty string
+        assert!(holds("env.UNSET == ''", &env_vars));
+        assert!(!holds("env.UNSET", &env_vars));
+        assert!(holds(
+            &format!("os == '{}' && family != 'none'", std::env::consts::OS),
+            &env_vars
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn evaluate_runs_success_with_the_environment() {
+        let env_vars = env(&[("BRAISE_CONDITION_TEST", "1")]);
+        assert!(holds(
+            "success('test \"$BRAISE_CONDITION_TEST\" = 1')",
+            &env_vars
+        ));
+        assert!(!holds("success('exit 1')", &env_vars));
+        assert!(holds(
+            "exists('Cargo.toml') && !exists('missing')",
+            &env_vars
+        ));
+    }
+}
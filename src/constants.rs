@@ -12,12 +12,154 @@ pub const FILE_NAMES: [&str; 8] = [
     "braiserc",
 ];
 
-pub const SCHEMA_URL: &str =
-    "https://raw.githubusercontent.com/cestef/braise/main/braise.schema.json";
+/// The environment variable holding the path of the Braise file to use, like `--file`
+pub const FILE_ENV_VAR: &str = "BRAISE_FILE";
+
+/// The files `braise migrate` converts when none is given, in order of precedence
+pub const MIGRATE_SOURCES: [&str; 7] = [
+    "justfile",
+    "Justfile",
+    ".justfile",
+    "Makefile",
+    "makefile",
+    "GNUmakefile",
+    "package.json",
+];
+
+/// The extensions a Braisefile can have, in order of precedence. Files without one are TOML
+pub const FILE_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+/// The URL of the JSON schema matching the running version, as published with its release tag
+pub fn schema_url() -> String {
+    format!(
+        "https://raw.githubusercontent.com/cestef/braise/v{}/braise.schema.json",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// The aliases of the task fields, mirroring the `#[serde(alias)]`s of `BraiseTask` (a test checks
+/// that they match)
+pub const TASK_FIELD_ALIASES: [(&str, &[&str]); 8] = [
+    ("command", &["cmd"]),
+    ("description", &["desc"]),
+    ("dependencies", &["deps", "depends", "depends_on"]),
+    ("shell", &["sh"]),
+    (
+        "runs_on",
+        &["runs-on", "run-on", "run_on", "os", "platform"],
+    ),
+    ("when", &["if"]),
+    ("all_features", &["all-features"]),
+    ("no_default_features", &["no-default-features"]),
+];
 
 lazy_static! {
     pub static ref ARG_REPLACE_REGEX: Regex = Regex::new(r"\{\d\}").unwrap(); // {0}, {1}, {2}, etc.
-    pub static ref ENV_REPLACE_REGEX: Regex = Regex::new(r"\{env\((\w+(?:\:(.+))?)\)\}").unwrap(); // {env(VAR)} or {env(VAR:default)}
+    pub static ref ENV_REPLACE_REGEX: Regex = Regex::new(r"\{env\((\w+)(?::(.*?))?\)\}").unwrap(); // {env(VAR)} or {env(VAR:default)}
 }
 
+/// The table holding the settings of a Braise file
+pub const SETTINGS_TABLE: &str = "braise";
+
+/// Top-level keys read as settings when a file has no `[braise]` table
+pub const LEGACY_SETTINGS_KEYS: [&str; 8] = [
+    "shell", "quiet", "default", "dotenv", "parallel", "strict", "import", "env",
+];
+
 pub const TASKS_SEPARATOR: char = ',';
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use schemars::{schema_for, JsonSchema};
+    use serde::{
+        de::{self, value::Error, Visitor},
+        forward_to_deserialize_any, Deserialize, Serialize,
+    };
+
+    use super::TASK_FIELD_ALIASES;
+    use crate::task::{BraiseArg, BraiseTask};
+
+    /// A deserializer recording the names serde accepts for the fields of a struct, aliases
+    /// included
+    struct FieldNames(&'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for &mut FieldNames {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Error> {
+            self.0 = fields;
+            Err(de::Error::custom("fields recorded"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    /// The aliases serde accepts for the fields of `T`
+    fn serde_aliases<'de, T: Deserialize<'de> + JsonSchema>() -> BTreeSet<String> {
+        let mut names = FieldNames(&[]);
+        let _ = T::deserialize(&mut names);
+        let schema = schema_for!(T);
+        let fields = schema
+            .get("properties")
+            .and_then(|properties| properties.as_object())
+            .expect("structs have properties");
+        names
+            .0
+            .iter()
+            .filter(|name| !fields.contains_key(**name))
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Whether `alias` is read as `field` by `T`, trying values of the usual types
+    fn reads_as<T: for<'de> Deserialize<'de> + Serialize>(alias: &str, field: &str) -> bool {
+        let values = [
+            toml::Value::String("value".to_string()),
+            toml::Value::Boolean(true),
+            toml::Value::Array(vec![toml::Value::String("value".to_string())]),
+        ];
+        values.into_iter().any(|value| {
+            let table = toml::Table::from_iter([(alias.to_string(), value)]);
+            toml::Value::Table(table)
+                .try_into::<T>()
+                .ok()
+                .and_then(|parsed| toml::Value::try_from(parsed).ok())
+                .is_some_and(|parsed| parsed.get(field).is_some())
+        })
+    }
+
+    #[test]
+    fn task_field_aliases_match_serde() {
+        let listed = TASK_FIELD_ALIASES
+            .iter()
+            .flat_map(|(_, aliases)| aliases.iter().map(|alias| alias.to_string()))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(serde_aliases::<BraiseTask>(), listed);
+        assert!(serde_aliases::<BraiseArg>().is_subset(&listed));
+        for (field, aliases) in TASK_FIELD_ALIASES {
+            for alias in aliases {
+                assert!(
+                    reads_as::<BraiseTask>(alias, field),
+                    "{} isn't an alias of {}",
+                    alias,
+                    field
+                );
+            }
+        }
+    }
+}
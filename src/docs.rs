@@ -0,0 +1,252 @@
+use clap::ValueEnum;
+use log::trace;
+
+use crate::{
+    file::{BraiseFile, TaskInfo},
+    utils::env_requirements,
+};
+
+/// The output format of `--docs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DocsFormat {
+    /// A Markdown document, e.g. to check in as `TASKS.md`
+    Markdown,
+    /// A roff man page
+    Man,
+}
+
+/// Render the documentation of every listed task of a Braisefile
+pub fn render_docs(file: &BraiseFile, path: &str, format: DocsFormat) -> String {
+    trace!("render_docs: entering");
+    let tasks = file.task_infos();
+    // Ungrouped tasks come first, then each group in order of appearance
+    let mut groups: Vec<Option<&str>> = vec![None];
+    for task in &tasks {
+        if !groups.contains(&task.group) {
+            groups.push(task.group);
+        }
+    }
+    let sections = groups
+        .into_iter()
+        .map(|group| {
+            (
+                group,
+                tasks
+                    .iter()
+                    .filter(|task| task.group == group)
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .filter(|(_, tasks)| !tasks.is_empty())
+        .collect::<Vec<_>>();
+    let docs = match format {
+        DocsFormat::Markdown => render_markdown(file, path, &sections),
+        DocsFormat::Man => render_man(file, path, &sections),
+    };
+    trace!("render_docs: exiting");
+    docs
+}
+
+type Sections<'a> = [(Option<&'a str>, Vec<&'a TaskInfo<'a>>)];
+
+/// The commands of every variant of a task, with the platforms they run on
+fn commands<'a>(file: &'a BraiseFile, task: &TaskInfo) -> Vec<(Option<String>, &'a str)> {
+    file.variants(task.name)
+        .into_iter()
+        .flatten()
+        .filter_map(|variant| {
+            let command = if variant.workspace.unwrap_or(false) {
+                "(cargo workspace binary)"
+            } else {
+                variant.command.as_deref()?
+            };
+            Some((variant.runs_on.as_ref().map(|os| os.join(", ")), command))
+        })
+        .collect()
+}
+
+fn render_markdown(file: &BraiseFile, path: &str, sections: &Sections) -> String {
+    let mut blocks = vec![
+        "# Tasks".to_string(),
+        format!(
+            "The tasks defined in `{}`, run them with `braise <task>`.",
+            path
+        ),
+    ];
+    for (group, tasks) in sections {
+        let heading = if let Some(group) = group {
+            blocks.push(format!("## {}", group));
+            "###"
+        } else if sections.len() > 1 {
+            "###"
+        } else {
+            "##"
+        };
+        for task in tasks {
+            blocks.push(format!("{} `{}`", heading, task.name));
+            if let Some(desc) = task.description {
+                blocks.push(desc.to_string());
+            }
+            let mut details = vec![];
+            if task.default {
+                details.push("- **Default task**".to_string());
+            }
+            if !task.aliases.is_empty() {
+                details.push(format!(
+                    "- **Aliases:** {}",
+                    task.aliases
+                        .iter()
+                        .map(|alias| format!("`{}`", alias))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            if let Some(ref platforms) = task.platforms {
+                details.push(format!("- **Platforms:** {}", platforms.join(", ")));
+            }
+            if let Some(origin) = task.origin {
+                details.push(format!("- **Imported from:** `{}`", origin));
+            }
+            if !task.dependencies.is_empty() {
+                details.push(format!(
+                    "- **Dependencies:** {}",
+                    task.dependencies
+                        .iter()
+                        .map(|dep| format!("`{}`", dep))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            let commands = commands(file, task);
+            let env = commands
+                .iter()
+                .flat_map(|(_, command)| env_requirements(command))
+                .map(|(name, default)| match default {
+                    Some(default) => format!("`{}` (default: `{}`)", name, default),
+                    None => format!("`{}`", name),
+                })
+                .collect::<Vec<_>>();
+            if !env.is_empty() {
+                details.push(format!("- **Environment:** {}", env.join(", ")));
+            }
+            if !details.is_empty() {
+                blocks.push(details.join("\n"));
+            }
+            if !task.args.is_empty() {
+                let mut table = vec![
+                    "| Argument | Description | Default | Choices |".to_string(),
+                    "| --- | --- | --- | --- |".to_string(),
+                ];
+                for arg in task.args {
+                    table.push(format!(
+                        "| `{}` | {} | {} | {} |",
+                        arg.name,
+                        arg.description.as_deref().unwrap_or_default(),
+                        arg.default
+                            .as_ref()
+                            .map(|default| format!("`{}`", default))
+                            .unwrap_or_default(),
+                        arg.choices
+                            .iter()
+                            .flatten()
+                            .map(|choice| format!("`{}`", choice))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+                blocks.push(table.join("\n"));
+            }
+            for (platforms, command) in commands {
+                if let Some(platforms) =
+                    platforms.filter(|_| file.variants(task.name).is_some_and(|v| v.len() > 1))
+                {
+                    blocks.push(format!("On {}:", platforms));
+                }
+                blocks.push(format!("```sh\n{}\n```", command.trim_end()));
+            }
+        }
+    }
+    blocks.join("\n\n") + "\n"
+}
+
+/// Escape text for roff, so that it isn't parsed as a request or an escape sequence
+fn roff_escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_man(file: &BraiseFile, path: &str, sections: &Sections) -> String {
+    let mut out = format!(
+        ".TH BRAISE\\-TASKS 7\n.SH NAME\nbraise\\-tasks \\- the tasks defined in {}\n.SH SYNOPSIS\n.B braise\n.I task\n.RI [ args ...]\n",
+        roff_escape(path)
+    );
+    for (group, tasks) in sections {
+        out.push_str(&format!(
+            ".SH {}\n",
+            roff_escape(&group.unwrap_or("TASKS").to_uppercase())
+        ));
+        for task in tasks {
+            out.push_str(&format!(".TP\n.B {}\n", roff_escape(task.name)));
+            out.push_str(&format!(
+                "{}\n",
+                roff_escape(task.description.unwrap_or("No description."))
+            ));
+            let mut details = vec![];
+            if task.default {
+                details.push("Default task.".to_string());
+            }
+            if !task.aliases.is_empty() {
+                details.push(format!("Aliases: {}.", task.aliases.join(", ")));
+            }
+            if let Some(ref platforms) = task.platforms {
+                details.push(format!("Platforms: {}.", platforms.join(", ")));
+            }
+            if let Some(origin) = task.origin {
+                details.push(format!("Imported from {}.", origin));
+            }
+            if !task.dependencies.is_empty() {
+                details.push(format!("Dependencies: {}.", task.dependencies.join(", ")));
+            }
+            let commands = commands(file, task);
+            let env = commands
+                .iter()
+                .flat_map(|(_, command)| env_requirements(command))
+                .map(|(name, default)| match default {
+                    Some(default) => format!("{} (default: {})", name, default),
+                    None => name.to_string(),
+                })
+                .collect::<Vec<_>>();
+            if !env.is_empty() {
+                details.push(format!("Environment: {}.", env.join(", ")));
+            }
+            for detail in details {
+                out.push_str(&format!(".br\n{}\n", roff_escape(&detail)));
+            }
+            if !task.args.is_empty() {
+                out.push_str(".RS\n");
+                for arg in task.args {
+                    out.push_str(&format!(".TP\n.I {}\n", roff_escape(&arg.name)));
+                    let mut text = arg.description.clone().unwrap_or_default();
+                    if let Some(ref default) = arg.default {
+                        text.push_str(&format!(" (default: {})", default));
+                    }
+                    if let Some(ref choices) = arg.choices {
+                        text.push_str(&format!(" [{}]", choices.join(", ")));
+                    }
+                    out.push_str(&format!("{}\n", roff_escape(text.trim_start())));
+                }
+                out.push_str(".RE\n");
+            }
+        }
+    }
+    out
+}
@@ -4,26 +4,64 @@ use thiserror::Error;
 pub enum BraiseError {
     #[error("No Braise file found")]
     NoBraiseFileFound,
+    #[error("Braise file {0} not found")]
+    FileNotFound(String),
+    #[error("Found {1} problem(s) in {0}")]
+    InvalidFile(String, usize),
+    #[error("Invalid [{0}] table: {1}")]
+    InvalidSettings(&'static str, String),
+    #[error("Invalid {0} in {1}: {2}")]
+    InvalidSyntax(&'static str, String, String),
+    #[error("Can't import tasks from {0}, expected a package.json, Makefile or justfile")]
+    UnsupportedImport(String),
+    #[error("Can't migrate {0}, expected a package.json, Makefile or justfile")]
+    UnsupportedMigration(String),
+    #[error("{0} already exists, use --force to overwrite it")]
+    FileExists(String),
+    #[error("{0} is not a directory")]
+    NotADirectory(String),
+    #[error("Can't format {0}, only TOML Braisefiles can be formatted")]
+    UnsupportedFormatting(String),
+    #[error("{0} isn't formatted, run `braise fmt` to format it")]
+    Unformatted(String),
     #[error("Invalid file format: {0}")]
     InvalidFileFormat(String),
     #[error("Task not found: {0}")]
     TaskNotFound(String),
     #[error("Invalid task: {0}")]
     InvalidTask(String),
+    #[error("Unknown task: {0}. {1}")]
+    UnknownTask(String, String),
+    #[error("Task {0} is private and can only be used as a dependency of another task")]
+    PrivateTask(String),
+    #[error("Alias {0} of task {2} is already used by task {1}")]
+    AliasConflict(String, String, String),
+    #[error("Alias {0} of task {1} conflicts with the task of the same name")]
+    AliasShadowsTask(String, String),
     #[error("No task or default task found")]
     NoTask,
     #[error("Invalid argument index: {0}, only {1} arguments provided")]
     InvalidArgIndex(usize, usize),
+    #[error("Invalid value {1} for argument {0}, expected one of: {2}")]
+    InvalidArgChoice(String, String, String),
     #[error("Error: {0}")]
     Error(String),
     #[error("Invalid shell: {0}")]
     InvalidShell(String),
-    #[error("Invalid dependency: {0}")]
-    InvalidDependency(String),
+    #[error("Invalid dependency: {0}. {1}")]
+    InvalidDependency(String, String),
     #[error("No shell found")]
     NoShell,
     #[error("No valid task found for {0}")]
     NoValidTask(String),
+    #[error("Package {0} has no binary")]
+    NoBinary(String),
+    #[error("Package {0} has several binaries, name the task after one of them: {1}")]
+    AmbiguousBinary(String, String),
+    #[error("git {0} failed: {1}")]
+    Git(String, String),
+    #[error("Invalid condition {0}: {1}")]
+    InvalidCondition(String, String),
     #[error("Thread error")]
     ThreadError,
 }
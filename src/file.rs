@@ -1,25 +1,53 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use crate::{
-    constants::{FILE_NAMES, TASKS_SEPARATOR},
+    check::{format_diagnostics, Diagnostic},
+    condition::Condition,
+    constants::{
+        FILE_EXTENSIONS, FILE_NAMES, LEGACY_SETTINGS_KEYS, SETTINGS_TABLE, TASKS_SEPARATOR,
+    },
     error::BraiseError,
-    task::BraiseTask,
+    import::import_tasks,
+    task::{BoolOrU8, BraiseArg, BraiseTask, StringOrBool},
+    utils::{get_shell_command, with_task_env},
+    workspace::{cargo_tasks, is_cargo_task, CargoOptions},
+};
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{bail, Context, Result},
+    owo_colors::OwoColorize,
 };
-use color_eyre::{eyre::Result, owo_colors::OwoColorize};
-use either::Either;
+use indexmap::IndexMap;
 use log::debug;
-use serde::Deserialize;
+use regex::RegexBuilder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use strsim::damerau_levenshtein;
 
 pub fn find_file() -> Result<String> {
+    find_file_in(Path::new(""))
+}
+
+/// Find the Braisefile of `dir`, returning its path joined to `dir`
+pub fn find_file_in(dir: &Path) -> Result<String> {
     let mut found = None;
-    // With and without the .toml extension
-    let candidates = FILE_NAMES
+    // With every extension, then without one
+    let candidates = FILE_EXTENSIONS
         .iter()
-        .map(|name| format!("{}.toml", name))
+        .flat_map(|ext| {
+            FILE_NAMES
+                .iter()
+                .map(move |name| format!("{}.{}", name, ext))
+        })
         .chain(FILE_NAMES.iter().map(|name| name.to_string()));
     for candidate in candidates {
-        if std::path::Path::new(&candidate).exists() {
-            found = Some(candidate);
+        let candidate = dir.join(candidate);
+        if candidate.exists() {
+            found = Some(candidate.display().to_string());
             break;
         }
     }
@@ -30,156 +58,786 @@ pub fn find_file() -> Result<String> {
     }
 }
 
-pub fn print_tasks(file: &BraiseFile, path: String) {
-    let manifest = cargo_toml::Manifest::from_path("Cargo.toml");
+/// The format of a Braisefile, picked from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+
+    /// Parse the source of a Braisefile into the value read by `BraiseFile::from_value`
+    pub fn parse(self, path: &str, source: &str) -> Result<toml::Value> {
+        let res = match self {
+            Self::Toml => return Ok(toml::from_str(source)?),
+            Self::Yaml => serde_yaml::from_str(source).map_err(|err| ("YAML", err.to_string())),
+            Self::Json => serde_json::from_str(source).map_err(|err| ("JSON", err.to_string())),
+        };
+        let mut value: toml::Value = res.map_err(|(format, message)| {
+            BraiseError::InvalidSyntax(format, path.to_string(), message)
+        })?;
+        // Editors read the schema from this key, where TOML uses a `#:schema` comment
+        if let Some(table) = value.as_table_mut() {
+            table.remove("$schema");
+        }
+        Ok(value)
+    }
+}
+
+/// The output format of `--list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// Colored, human readable output
+    Plain,
+    /// A JSON array of tasks, for editors and scripts
+    Json,
+    /// One task name per line, e.g. for piping into `fzf`
+    Names,
+}
+
+/// The order in which `--list` prints tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    /// The order in which the tasks are defined in the file
+    File,
+    /// Alphabetical order
+    Name,
+}
+
+/// A machine-readable summary of a task, as printed by `--list --format json`
+#[derive(Debug, Serialize)]
+pub struct TaskInfo<'a> {
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+    pub group: Option<&'a str>,
+    pub aliases: Vec<&'a str>,
+    pub default: bool,
+    /// The platforms the task can run on, `None` meaning all of them
+    pub platforms: Option<Vec<&'a str>>,
+    pub dependencies: &'a [String],
+    pub args: &'a [BraiseArg],
+    /// The file the task was imported from
+    pub origin: Option<&'a str>,
+}
+
+pub fn print_tasks(
+    file: &BraiseFile,
+    path: String,
+    format: ListFormat,
+    sort: ListSort,
+    pattern: Option<&str>,
+) -> Result<()> {
+    let pattern = pattern
+        .map(|p| RegexBuilder::new(p).case_insensitive(true).build())
+        .transpose()
+        .context("Invalid pattern")?;
+    let mut tasks = file
+        .task_infos()
+        .into_iter()
+        .filter(|task| {
+            pattern.as_ref().is_none_or(|pattern| {
+                pattern.is_match(task.name)
+                    || task.aliases.iter().any(|alias| pattern.is_match(alias))
+                    || task.description.is_some_and(|desc| pattern.is_match(desc))
+                    || task.group.is_some_and(|group| pattern.is_match(group))
+                    || task.origin.is_some_and(|origin| pattern.is_match(origin))
+            })
+        })
+        .collect::<Vec<_>>();
+    if sort == ListSort::Name {
+        tasks.sort_by_key(|task| task.name);
+    }
+    match format {
+        ListFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&tasks)?);
+            return Ok(());
+        }
+        ListFormat::Names => {
+            for task in tasks {
+                println!("{}", task.name);
+            }
+            return Ok(());
+        }
+        ListFormat::Plain => {}
+    }
     println!(
         "{}",
         format!("Available tasks in {}:\n", path.bold()).underline()
     );
-    let maybe_defaults: Option<Vec<_>> = file
-        .default
-        .clone()
-        .map(|d| d.split(TASKS_SEPARATOR).map(|d| d.to_string()).collect());
-    for (task, scripts) in &file.tasks {
-        let is_default = if let Some(ref defaults) = maybe_defaults {
-            defaults.contains(&task)
-        } else {
-            false
-        };
-        for script in scripts {
+    // Ungrouped tasks come first, then each group in order of appearance
+    let mut groups: Vec<Option<&str>> = vec![None];
+    for task in &tasks {
+        if !groups.contains(&task.group) {
+            groups.push(task.group);
+        }
+    }
+    if sort == ListSort::Name {
+        groups.sort();
+    }
+    for group in groups {
+        let group_tasks = tasks.iter().filter(|task| task.group == group);
+        if let Some(group) = group {
+            println!("\n{}", format!("{}:", group).bold());
+        }
+        for task in group_tasks {
             println!(
-                "{}{}{}",
-                task.bold(),
-                if let Some(ref desc) = script.description {
+                "{}{}{}{}{}{}{}",
+                task.name.bold(),
+                if task.aliases.is_empty() {
+                    "".to_string()
+                } else {
+                    format!(" ({})", task.aliases.join(", "))
+                        .dimmed()
+                        .to_string()
+                },
+                task.args
+                    .iter()
+                    .map(|arg| format!(" <{}>", arg.name).dimmed().to_string())
+                    .collect::<String>(),
+                if let Some(desc) = task.description {
                     format!(": {}", desc.dimmed())
                 } else {
                     "".to_string()
                 },
-                if is_default {
+                if task.default {
                     " (default)".dimmed().to_string()
                 } else {
                     "".to_string()
+                },
+                if let Some(ref platforms) = task.platforms {
+                    format!(" [{}]", platforms.join(", ")).cyan().to_string()
+                } else {
+                    "".to_string()
+                },
+                if let Some(origin) = task.origin {
+                    format!(" [{}]", origin).yellow().to_string()
+                } else {
+                    "".to_string()
                 }
             );
         }
     }
-    if let Ok(manifest) = manifest {
-        if let Some(workspace) = manifest.workspace {
-            println!(
-                "{}",
-                format!("\nAvailable binaries in {}:\n", "Cargo.toml".bold()).underline()
-            );
-            for member in workspace.members {
-                let name = member
-                    .split('/')
-                    .last()
-                    .unwrap_or(&member)
-                    .split('.')
-                    .next()
-                    .unwrap_or(&member);
-                println!("{}", name.bold());
-            }
-        }
-    }
+    Ok(())
 }
 
-/// A struct representing a Braise file
-#[derive(Debug)]
-pub struct BraiseFile {
-    pub tasks: HashMap<String, Vec<BraiseTask>>,
+/// The settings of a Braise file, from its `[braise]` table
+/// ```toml
+/// [braise]
+/// shell = "bash -c"
+/// default = "build"
+/// ```
+/// Files without a `[braise]` table can set these as top-level keys instead.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BraiseSettings {
+    /// The shell used to run the commands
     pub shell: Option<String>,
-    pub quiet: Either<Option<bool>, Option<u8>>,
+    /// Whether to suppress output (q (1) = braise output, qq (2) = braise + command output)
+    pub quiet: Option<BoolOrU8>,
+    /// The tasks to run when none is given, separated by commas
     pub default: Option<String>,
-    pub dotenv: Either<Option<String>, Option<bool>>,
+    /// The .env file to load (string = path, bool = load `.env`)
+    pub dotenv: Option<StringOrBool>,
+    /// Whether to run tasks in parallel, `false` also running the packages of `foreach` tasks one
+    /// at a time
     pub parallel: Option<bool>,
+    /// Whether to fail on any problem found in the file instead of skipping what can't be parsed
+    pub strict: Option<bool>,
+    /// The `package.json`, Makefiles and justfiles to import tasks from
+    pub import: Option<Vec<String>>,
+    /// Environment variables set for every task, which can reference others with `{env(VAR)}`
+    pub env: Option<IndexMap<String, String>>,
+}
+
+/// A struct representing a Braise file
+#[derive(Debug)]
+pub struct BraiseFile {
+    pub tasks: IndexMap<String, Vec<BraiseTask>>,
+    /// Task aliases, mapped to the name of the task they refer to
+    pub aliases: HashMap<String, String>,
+    pub settings: BraiseSettings,
+    /// The problems found while reading the file
+    pub diagnostics: Vec<Diagnostic>,
+    /// The packages `foreach` tasks run for, all of them if `None`
+    pub packages: Option<Vec<String>>,
+    /// The parsed `when` conditions of the tasks, or why they're invalid
+    conditions: HashMap<String, Result<Condition, String>>,
+    /// The directory of the file, holding the Cargo project tasks are synthesized from
+    dir: PathBuf,
+    /// The tasks synthesized from the Cargo project, read the first time one is looked up
+    synthesized: OnceLock<IndexMap<String, Vec<BraiseTask>>>,
 }
 
 impl BraiseFile {
-    pub fn from_value(value: toml::Value) -> Result<Self> {
-        let tasks = value
-            .as_table()
-            .ok_or(BraiseError::InvalidFileFormat(
-                "File is not a table".to_string(),
-            ))?
-            .iter()
-            .filter_map(|(task, script)| {
-                if script.is_table() {
-                    let res = BraiseTask::deserialize(script.clone());
-                    if let Ok(script) = res {
-                        Some((task.clone(), vec![script]))
-                    } else {
-                        debug!("Couldn't parse task {}: {}", task.bold(), res.unwrap_err());
-                        None
-                    }
-                } else if script.is_array() {
-                    debug!("Task {} is an array", task);
-                    // Check if the array is a table
-                    let script = script.as_array().unwrap();
-                    if script.iter().all(|s| s.is_table()) {
-                        let scripts = script
-                            .iter()
-                            .filter_map(|s| {
-                                if let Ok(s) = BraiseTask::deserialize(s.clone()) {
-                                    Some(s)
-                                } else {
-                                    debug!("Couldn't parse task: {}", task);
-                                    None
-                                }
-                            })
-                            .collect();
+    pub fn from_path(path: &str) -> Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+        let value = FileFormat::from_path(path).parse(path, &source)?;
+        debug!("Parsed file: {:#?}", value);
 
-                        Some((task.clone(), scripts))
-                    } else {
-                        debug!("Task {} is not a table", task);
-                        None
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        let file = Self::from_value(value, dir)?;
+        debug!("Parsed braisé file: {:#?}", file);
+        if file.settings.strict.unwrap_or(false) && !file.diagnostics.is_empty() {
+            eprintln!("{}", format_diagnostics(path, &source, &file.diagnostics));
+            bail!(BraiseError::InvalidFile(
+                path.to_string(),
+                file.diagnostics.len()
+            ));
+        }
+        Ok(file)
+    }
+
+    /// Read a Braisefile without reporting its problems, even in strict mode
+    pub fn read(path: &str) -> Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+        let value = FileFormat::from_path(path).parse(path, &source)?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        Self::from_value(value, dir)
+    }
+
+    /// Read a Braisefile from its parsed value, with the files it imports relative to `dir`
+    pub fn from_value(value: toml::Value, dir: &Path) -> Result<Self> {
+        let table = value.as_table().ok_or(BraiseError::InvalidFileFormat(
+            "File is not a table".to_string(),
+        ))?;
+        let has_settings_table = table.get(SETTINGS_TABLE).is_some_and(is_settings_table);
+        // With a `[braise]` table every other table is a task, otherwise the legacy top-level
+        // keys are settings unless they are tables, except `env` which is a table unless it is a
+        // task
+        let is_setting = |key: &str, value: &toml::Value| {
+            if has_settings_table {
+                key == SETTINGS_TABLE
+            } else if key == "env" {
+                is_settings_table(value)
+            } else {
+                LEGACY_SETTINGS_KEYS.contains(&key) && !is_task_value(value)
+            }
+        };
+
+        let mut diagnostics = vec![];
+        let mut tasks = IndexMap::new();
+        // The keys and raw value of every parsed task variant, to report problems found later
+        let mut sources: HashMap<&str, Vec<(Vec<String>, &toml::Value)>> = HashMap::new();
+        for (task, script) in table {
+            if is_setting(task, script) {
+                continue;
+            }
+            if script.is_table() {
+                let keys = vec![task.clone()];
+                if let Some(parsed) = parse_task(script, keys.clone(), &mut diagnostics) {
+                    tasks.insert(task.clone(), vec![parsed]);
+                    sources.insert(task, vec![(keys, script)]);
+                }
+            } else if is_task_value(script) {
+                debug!("Task {} is an array", task);
+                let mut scripts = vec![];
+                for (index, variant) in script.as_array().unwrap().iter().enumerate() {
+                    let keys = vec![task.clone(), index.to_string()];
+                    if let Some(parsed) = parse_task(variant, keys.clone(), &mut diagnostics) {
+                        scripts.push(parsed);
+                        sources.entry(task).or_default().push((keys, variant));
                     }
-                } else {
-                    debug!("Task {} is not a table", task);
-                    None
                 }
-            })
-            .collect::<HashMap<_, Vec<_>>>();
-
-        let shell = value
-            .get("shell")
-            .map(|s| s.as_str().map(|s| s.to_string()))
-            .flatten();
-
-        let quiet = if let Some(quiet) = value.get("quiet") {
-            if quiet.is_bool() {
-                Either::Left(Some(quiet.as_bool().unwrap()))
-            } else if quiet.is_integer() {
-                Either::Right(Some(quiet.as_integer().unwrap() as u8))
+                tasks.insert(task.clone(), scripts);
             } else {
-                Either::Left(None)
+                debug!("Task {} is not a table", task);
+                diagnostics.push(Diagnostic::new(
+                    vec![task.clone()],
+                    if has_settings_table {
+                        format!(
+                            "Unknown key {}, settings go in the [{}] table",
+                            task, SETTINGS_TABLE
+                        )
+                    } else {
+                        format!("Unknown key {}, tasks must be tables", task)
+                    },
+                ));
             }
+        }
+
+        let (settings, settings_keys) = if has_settings_table {
+            let settings = serde_path_to_error::deserialize(table[SETTINGS_TABLE].clone())
+                .map_err(|err| {
+                    BraiseError::InvalidSettings(
+                        SETTINGS_TABLE,
+                        err.to_string().trim_end().to_string(),
+                    )
+                })?;
+            (settings, vec![SETTINGS_TABLE.to_string()])
         } else {
-            Either::Left(None)
+            let flat = table
+                .iter()
+                .filter(|(key, value)| is_setting(key, value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            (parse_legacy_settings(flat, &mut diagnostics), vec![])
         };
 
-        let default = value.get("default").map(|d| d.as_str()).flatten();
+        for (index, import) in settings.import.iter().flatten().enumerate() {
+            let imported = match import_tasks(dir, import) {
+                Ok(imported) => imported,
+                Err(err) => {
+                    diagnostics.push(Diagnostic::new(
+                        [
+                            settings_keys.clone(),
+                            vec!["import".to_string(), index.to_string()],
+                        ]
+                        .concat(),
+                        format!("Couldn't import {}: {}", import, err.root_cause()),
+                    ));
+                    continue;
+                }
+            };
+            // Tasks of the Braisefile, and those imported first, take precedence
+            for (name, task) in imported {
+                if table.contains_key(&name) || tasks.contains_key(&name) {
+                    debug!(
+                        "Not importing {} from {}, it is already defined",
+                        name, import
+                    );
+                    continue;
+                }
+                tasks.insert(name, vec![task]);
+            }
+        }
 
-        let dotenv = if let Some(dotenv) = value.get("dotenv") {
-            if dotenv.is_str() {
-                Either::Left(Some(dotenv.as_str().unwrap().to_string()))
-            } else if dotenv.is_bool() {
-                Either::Right(Some(dotenv.as_bool().unwrap()))
-            } else {
-                Either::Left(None)
+        let mut aliases = HashMap::new();
+        for (name, variants) in &tasks {
+            for alias in variants
+                .iter()
+                .flat_map(|task| task.aliases.iter().flatten())
+            {
+                if tasks.contains_key(alias) {
+                    bail!(BraiseError::AliasShadowsTask(
+                        alias.to_string(),
+                        name.to_string()
+                    ));
+                }
+                match aliases.insert(alias.to_string(), name.to_string()) {
+                    Some(other) if other != *name => {
+                        bail!(BraiseError::AliasConflict(
+                            alias.to_string(),
+                            other,
+                            name.to_string()
+                        ));
+                    }
+                    _ => {}
+                }
             }
-        } else {
-            Either::Left(None)
+        }
+
+        // Conditions are only parsed once, an invalid one failing the task when it's picked
+        let conditions = tasks
+            .values()
+            .flatten()
+            .filter_map(|task| task.when.as_ref())
+            .map(|when| (when.to_string(), Condition::parse(when)))
+            .collect::<HashMap<_, _>>();
+
+        // Tasks that failed to parse are already reported, so they still count as defined here
+        let synthesized = OnceLock::new();
+        let resolves = |name: &str| {
+            table.get(name).is_some_and(|task| !is_setting(name, task))
+                || tasks.contains_key(name)
+                || aliases.contains_key(name)
+                || (is_cargo_task(name)
+                    && synthesized
+                        .get_or_init(|| synthesize_cargo_tasks(dir))
+                        .contains_key(name))
         };
+        for (name, variants) in &tasks {
+            for (task, (keys, variant)) in variants
+                .iter()
+                .zip(sources.get(name.as_str()).into_iter().flatten())
+            {
+                if !task.workspace.unwrap_or(false)
+                    && CargoOptions::from_task(task) != CargoOptions::default()
+                {
+                    diagnostics.push(Diagnostic::new(
+                        keys.clone(),
+                        format!(
+                            "The cargo options of task {} only apply with workspace = true",
+                            name
+                        ),
+                    ));
+                }
+                if let Some((when, Err(reason))) = task
+                    .when
+                    .as_ref()
+                    .and_then(|when| conditions.get_key_value(when))
+                {
+                    let field = variant.as_table().and_then(|table| {
+                        ["when", "if"]
+                            .into_iter()
+                            .find(|field| table.contains_key(*field))
+                    });
+                    let mut keys = keys.clone();
+                    keys.extend(field.map(|field| field.to_string()));
+                    diagnostics.push(Diagnostic::new(
+                        keys,
+                        BraiseError::InvalidCondition(when.to_string(), reason.to_string())
+                            .to_string(),
+                    ));
+                }
+                for (index, dep) in task.dependencies.iter().flatten().enumerate() {
+                    if !resolves(dep) {
+                        // Point at the dependency under whichever alias of the field is used
+                        let field = variant.as_table().and_then(|table| {
+                            table.iter().find_map(|(key, value)| {
+                                value
+                                    .as_array()
+                                    .is_some_and(|deps| {
+                                        deps.get(index).and_then(|d| d.as_str()) == Some(dep)
+                                    })
+                                    .then_some(key.clone())
+                            })
+                        });
+                        let mut keys = keys.clone();
+                        keys.extend(field.into_iter().chain([index.to_string()]));
+                        diagnostics.push(Diagnostic::new(
+                            keys,
+                            format!("Unknown dependency {} of task {}", dep, name),
+                        ));
+                    }
+                }
+            }
+        }
+        for default in settings
+            .default
+            .iter()
+            .flat_map(|d| d.split(TASKS_SEPARATOR))
+        {
+            if !resolves(default) {
+                diagnostics.push(Diagnostic::new(
+                    [settings_keys.clone(), vec!["default".to_string()]].concat(),
+                    format!("Unknown default task {}", default),
+                ));
+            }
+        }
 
-        let parallel = value.get("parallel").map(|p| p.as_bool()).flatten();
+        for diagnostic in &diagnostics {
+            debug!("{}: {}", diagnostic.keys.join("."), diagnostic.message);
+        }
 
         Ok(Self {
             tasks,
-            shell,
-            quiet,
-            default: default.map(|d| d.to_string()),
-            dotenv,
-            parallel,
+            aliases,
+            settings,
+            diagnostics,
+            packages: None,
+            conditions,
+            dir: dir.to_path_buf(),
+            synthesized,
+        })
+    }
+
+    /// The tasks run when no task is given on the command line
+    pub fn defaults(&self) -> Vec<&str> {
+        self.settings
+            .default
+            .as_deref()
+            .map(|d| d.split(TASKS_SEPARATOR).collect())
+            .unwrap_or_default()
+    }
+
+    /// Resolve a task name or alias to the name of the task
+    pub fn resolve(&self, name: &str) -> Option<&str> {
+        if let Some((name, _)) = self.tasks.get_key_value(name) {
+            Some(name)
+        } else if let Some(name) = self.aliases.get(name) {
+            Some(name)
+        } else if is_cargo_task(name) {
+            self.synthesized()
+                .get_key_value(name)
+                .map(|(name, _)| name.as_str())
+        } else {
+            None
+        }
+    }
+
+    /// The directory of the file, the root of the Cargo project its tasks are synthesized from,
+    /// run in and found affected from
+    pub fn dir(&self) -> &Path {
+        if self.dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.dir
+        }
+    }
+
+    /// The tasks of the file, then those synthesized from the Cargo project that it doesn't
+    /// define. Listing them reads the Cargo project
+    pub fn all_tasks(&self) -> impl Iterator<Item = (&String, &Vec<BraiseTask>)> {
+        self.tasks.iter().chain(
+            self.synthesized()
+                .iter()
+                .filter(|(name, _)| !self.tasks.contains_key(*name)),
+        )
+    }
+
+    /// The tasks synthesized from the Cargo project, which every load would otherwise walk
+    fn synthesized(&self) -> &IndexMap<String, Vec<BraiseTask>> {
+        self.synthesized
+            .get_or_init(|| synthesize_cargo_tasks(&self.dir))
+    }
+
+    /// The variants of a task (or alias), including the synthesized tasks
+    pub fn variants(&self, name: &str) -> Option<&Vec<BraiseTask>> {
+        let name = self.resolve(name)?;
+        self.tasks
+            .get(name)
+            .or_else(|| self.synthesized().get(name))
+    }
+
+    /// A hint listing the tasks closest to an unknown name, or all the tasks when none are close
+    pub fn suggest(&self, name: &str, include_private: bool) -> String {
+        let candidates = self
+            .tasks
+            .iter()
+            .filter(|(task, variants)| {
+                include_private || !variants.iter().all(|variant| variant.is_private(task))
+            })
+            .map(|(task, _)| task)
+            .chain(
+                self.aliases
+                    .iter()
+                    .filter(|(_, task)| {
+                        include_private
+                            || !self.tasks[task.as_str()]
+                                .iter()
+                                .all(|variant| variant.is_private(task))
+                    })
+                    .map(|(alias, _)| alias),
+            )
+            .map(|candidate| candidate.as_str())
+            .collect::<Vec<_>>();
+        let threshold = (name.len() / 3).max(1);
+        let mut close = candidates
+            .iter()
+            .map(|candidate| (damerau_levenshtein(name, candidate), *candidate))
+            .filter(|(distance, _)| *distance <= threshold)
+            .collect::<Vec<_>>();
+        close.sort();
+        if close.is_empty() {
+            let mut available = candidates;
+            available.sort();
+            format!("Available tasks: {}", available.join(", "))
+        } else {
+            format!(
+                "Did you mean {}?",
+                close
+                    .iter()
+                    .take(3)
+                    .map(|(_, candidate)| *candidate)
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            )
+        }
+    }
+
+    /// Find the variant of a task (or alias) that can run on the current OS and whose `when`
+    /// condition holds in the environment `env_vars` and the `env` of the variant. Otherwise,
+    /// gives the condition of the first variant skipped because of it, if any
+    pub fn pick_task(
+        &self,
+        name: &str,
+        env_vars: &HashMap<String, String>,
+    ) -> Result<Result<&BraiseTask, Option<&str>>> {
+        let Some(variants) = self.variants(name) else {
+            return Ok(Err(None));
+        };
+        let mut skipped = None;
+        for task in variants.iter().filter(|task| task.matches_os()) {
+            let Some(when) = &task.when else {
+                return Ok(Ok(task));
+            };
+            let condition = match self.conditions.get(when) {
+                Some(Ok(condition)) => condition,
+                Some(Err(reason)) => {
+                    bail!(BraiseError::InvalidCondition(
+                        when.to_string(),
+                        reason.to_string()
+                    ));
+                }
+                None => unreachable!("the conditions of every task are parsed when loading"),
+            };
+            let env_vars = with_task_env(env_vars, self, task)?;
+            let holds = condition.evaluate(&get_shell_command(task, self), &env_vars);
+            debug!("Condition {} is {}", when, holds);
+            if holds {
+                return Ok(Ok(task));
+            }
+            skipped = skipped.or(Some(when.as_str()));
+        }
+        Ok(Err(skipped))
+    }
+
+    /// The variant of a task describing it in listings: the first one for the current OS, or the
+    /// first one. `when` conditions aren't evaluated, as they can run commands
+    pub fn describe_task(&self, name: &str) -> Option<&BraiseTask> {
+        let variants = self.variants(name)?;
+        variants
+            .iter()
+            .find(|task| task.matches_os())
+            .or(variants.first())
+    }
+
+    /// Summarize every listed task, picking the variant for the current OS (or the first one)
+    pub fn task_infos(&self) -> Vec<TaskInfo<'_>> {
+        let defaults = self.defaults();
+        self.all_tasks()
+            .filter(|(name, variants)| !variants.iter().all(|variant| variant.is_hidden(name)))
+            .filter_map(|(name, variants)| {
+                let task = self.describe_task(name)?;
+                let platforms = variants
+                    .iter()
+                    .map(|variant| variant.runs_on.as_ref())
+                    .collect::<Option<Vec<_>>>()
+                    .map(|platforms| {
+                        platforms.into_iter().flatten().fold(vec![], |mut acc, os| {
+                            if !acc.contains(&os.as_str()) {
+                                acc.push(os.as_str());
+                            }
+                            acc
+                        })
+                    });
+                Some(TaskInfo {
+                    name,
+                    description: task.description.as_deref(),
+                    group: variants.iter().find_map(|variant| variant.group.as_deref()),
+                    aliases: variants
+                        .iter()
+                        .flat_map(|variant| variant.aliases.iter().flatten())
+                        .fold(vec![], |mut acc, alias| {
+                            if !acc.contains(&alias.as_str()) {
+                                acc.push(alias.as_str());
+                            }
+                            acc
+                        }),
+                    default: defaults.contains(&name.as_str()),
+                    platforms,
+                    dependencies: task.dependencies.as_deref().unwrap_or_default(),
+                    args: task.args.as_deref().unwrap_or_default(),
+                    origin: task.origin.as_deref(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Deserialize a task, recording why it couldn't be parsed and any unknown key
+fn parse_task(
+    value: &toml::Value,
+    keys: Vec<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<BraiseTask> {
+    let mut ignored = vec![];
+    let res = serde_path_to_error::deserialize(serde_ignored::Deserializer::new(
+        value.clone(),
+        &mut |path: serde_ignored::Path| ignored.push(path.to_string()),
+    ));
+    for path in ignored {
+        // `?` marks an `Option` in the path, it isn't a key of the file
+        let path = path
+            .split('.')
+            .filter(|key| *key != "?")
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>();
+        let message = format!("Unknown key {}", path.join("."));
+        diagnostics.push(Diagnostic::new([keys.clone(), path].concat(), message));
+    }
+    match res {
+        Ok(task) => Some(task),
+        Err(err) => {
+            let mut keys = keys.clone();
+            keys.extend(err.path().iter().map(|segment| segment.to_string()));
+            diagnostics.push(Diagnostic::new(
+                keys.clone(),
+                format!(
+                    "Couldn't parse task {}: {}",
+                    keys[0],
+                    err.inner().to_string().lines().next().unwrap_or_default()
+                ),
+            ));
+            None
+        }
+    }
+}
+
+/// Whether a top-level value defines a task, either as a table or an array of tables
+fn is_task_value(value: &toml::Value) -> bool {
+    value.is_table()
+        || value
+            .as_array()
+            .is_some_and(|s| s.iter().all(|s| s.is_table()))
+}
+
+/// The binaries, examples, benchmarks and tests of the Cargo project in `dir`, if any
+fn synthesize_cargo_tasks(dir: &Path) -> IndexMap<String, Vec<BraiseTask>> {
+    if !dir.join("Cargo.toml").is_file() {
+        return IndexMap::new();
+    }
+    match cargo_tasks(dir) {
+        Ok(synthesized) => synthesized
+            .into_iter()
+            .map(|(name, task)| (name, vec![task]))
+            .collect(),
+        Err(err) => {
+            debug!("Couldn't read the Cargo project: {:#}", err);
+            IndexMap::new()
+        }
+    }
+}
+
+/// Whether a `[braise]` table (or a top-level `env` table) holds the settings rather than a task
+/// of that name, which files written before the table existed can define. A table with a command, dependencies or
+/// `workspace` is a task
+pub fn is_settings_table(value: &toml::Value) -> bool {
+    value.is_table()
+        && !value.clone().try_into::<BraiseTask>().is_ok_and(|task| {
+            task.command.is_some() || task.dependencies.is_some() || task.workspace.is_some()
         })
+}
+
+/// Deserialize the top-level settings of a file without a `[braise]` table, skipping and
+/// recording the ones that can't be parsed
+fn parse_legacy_settings(
+    mut flat: toml::Table,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> BraiseSettings {
+    loop {
+        match serde_path_to_error::deserialize(toml::Value::Table(flat.clone())) {
+            Ok(settings) => return settings,
+            Err(err) => {
+                let message = err.inner().to_string();
+                let Some(key) = err
+                    .path()
+                    .iter()
+                    .next()
+                    .map(|key| key.to_string())
+                    .filter(|key| flat.remove(key).is_some())
+                else {
+                    debug!("Couldn't parse settings: {}", message);
+                    return BraiseSettings::default();
+                };
+                diagnostics.push(Diagnostic::new(
+                    vec![key.clone()],
+                    format!(
+                        "Invalid setting {}: {}",
+                        key,
+                        message.lines().next().unwrap_or_default()
+                    ),
+                ));
+            }
+        }
     }
 }
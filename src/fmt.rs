@@ -0,0 +1,311 @@
+use color_eyre::{
+    eyre::{bail, Result},
+    owo_colors::OwoColorize,
+};
+use log::{debug, trace};
+use schemars::{schema_for, JsonSchema};
+use toml_edit::{ArrayOfTables, DocumentMut, InlineTable, Item, Key, Table, Value};
+
+use crate::{
+    constants::{SETTINGS_TABLE, TASK_FIELD_ALIASES},
+    error::BraiseError,
+    file::{is_settings_table, BraiseSettings, FileFormat},
+    task::{BraiseArg, BraiseTask},
+};
+
+/// The order of the fields of a Braisefile's tables, as they are declared
+struct FieldOrder {
+    settings: Vec<String>,
+    task: Vec<String>,
+    arg: Vec<String>,
+}
+
+/// The fields of a type in the order they are declared, as listed by its JSON schema
+fn fields<T: JsonSchema>() -> Vec<String> {
+    schema_for!(T)
+        .get("properties")
+        .and_then(|properties| properties.as_object())
+        .map(|properties| properties.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// The position of `key` among `fields`, unknown keys going last in their original order
+fn rank(fields: &[String], key: &Key) -> usize {
+    fields
+        .iter()
+        .position(|field| field == key.get())
+        .unwrap_or(fields.len())
+}
+
+/// The aliases used among `keys`, with the field they stand for. An alias is kept when its field
+/// is also set, serde refusing the duplicate anyway
+fn renames(fields: &[String], keys: &[&str]) -> Vec<(String, &'static str)> {
+    TASK_FIELD_ALIASES
+        .iter()
+        .filter(|(field, _)| fields.iter().any(|f| f == field) && !keys.contains(field))
+        .flat_map(|(field, aliases)| {
+            aliases
+                .iter()
+                .filter(|alias| keys.contains(alias))
+                .map(|alias| (alias.to_string(), *field))
+        })
+        .collect()
+}
+
+/// The same key under another name, keeping its comments and whitespace
+fn renamed(key: &Key, name: &str) -> Key {
+    Key::new(name)
+        .with_leaf_decor(key.leaf_decor().clone())
+        .with_dotted_decor(key.dotted_decor().clone())
+}
+
+impl FieldOrder {
+    fn new() -> Self {
+        Self {
+            settings: fields::<BraiseSettings>(),
+            task: fields::<BraiseTask>(),
+            arg: fields::<BraiseArg>(),
+        }
+    }
+
+    fn format_table(&self, table: &mut Table, fields: &[String]) {
+        for (alias, field) in renames(
+            fields,
+            &table.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+        ) {
+            if let Some((key, item)) = table.remove_entry(&alias) {
+                debug!("Renaming {} to {}", alias, field);
+                table.insert_formatted(&renamed(&key, field), item);
+            }
+        }
+        table.sort_values_by(|a, _, b, _| rank(fields, a).cmp(&rank(fields, b)));
+    }
+
+    fn format_inline_table(&self, table: &mut InlineTable, fields: &[String]) {
+        for (alias, field) in renames(
+            fields,
+            &table.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+        ) {
+            if let Some((key, value)) = table.remove_entry(&alias) {
+                debug!("Renaming {} to {}", alias, field);
+                table.insert_formatted(&renamed(&key, field), value);
+            }
+        }
+        table.sort_values_by(|a, _, b, _| rank(fields, a).cmp(&rank(fields, b)));
+        // Inline tables can't hold comments, so their spacing can be reset once reordered
+        table.fmt();
+    }
+
+    fn format_task_table(&self, table: &mut Table) {
+        self.format_table(table, &self.task);
+        match table.get_mut("args") {
+            Some(Item::ArrayOfTables(args)) => {
+                args.iter_mut()
+                    .for_each(|arg| self.format_table(arg, &self.arg));
+            }
+            Some(Item::Value(args)) => self.format_args(args),
+            _ => {}
+        }
+    }
+
+    fn format_inline_task(&self, table: &mut InlineTable) {
+        self.format_inline_table(table, &self.task);
+        if let Some(args) = table.get_mut("args") {
+            self.format_args(args);
+        }
+    }
+
+    fn format_args(&self, args: &mut Value) {
+        if let Value::Array(args) = args {
+            args.iter_mut()
+                .filter_map(Value::as_inline_table_mut)
+                .for_each(|arg| self.format_inline_table(arg, &self.arg));
+        }
+    }
+
+    fn format_tasks(&self, tasks: &mut ArrayOfTables) {
+        tasks
+            .iter_mut()
+            .for_each(|task| self.format_task_table(task));
+    }
+}
+
+/// Rewrite a TOML Braisefile with the canonical names of the fields, in the order they are
+/// declared, keeping its comments and the order of its tasks
+pub fn format_source(source: &str) -> Result<String> {
+    trace!("format_source: entering");
+    let mut document = source.parse::<DocumentMut>()?;
+    let order = FieldOrder::new();
+    let value = toml::from_str::<toml::Value>(source)?;
+    let has_settings_table = value.get(SETTINGS_TABLE).is_some_and(is_settings_table);
+    // Without a `[braise]` table, a top-level `env` table holds variables, not task fields
+    let has_env_table = !has_settings_table && value.get("env").is_some_and(is_settings_table);
+    for (key, item) in document.iter_mut() {
+        if has_env_table && key.get() == "env" {
+            continue;
+        }
+        if has_settings_table && key.get() == SETTINGS_TABLE {
+            match item {
+                Item::Table(settings) => order.format_table(settings, &order.settings),
+                Item::Value(Value::InlineTable(settings)) => {
+                    order.format_inline_table(settings, &order.settings)
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match item {
+            Item::Table(task) => order.format_task_table(task),
+            Item::ArrayOfTables(tasks) => order.format_tasks(tasks),
+            Item::Value(Value::InlineTable(task)) => order.format_inline_task(task),
+            Item::Value(Value::Array(tasks)) => tasks
+                .iter_mut()
+                .filter_map(Value::as_inline_table_mut)
+                .for_each(|task| order.format_inline_task(task)),
+            _ => {}
+        }
+    }
+    // Without a `[braise]` table the settings are the top-level keys, which come before any table
+    if !has_settings_table {
+        document
+            .sort_values_by(|a, _, b, _| rank(&order.settings, a).cmp(&rank(&order.settings, b)));
+    }
+    trace!("format_source: exiting");
+    Ok(document.to_string())
+}
+
+/// Format a Braisefile in place, or only report whether it is formatted with `check`
+pub fn format_file(path: &str, check: bool) -> Result<()> {
+    trace!("format_file: entering");
+    if FileFormat::from_path(path) != FileFormat::Toml {
+        bail!(BraiseError::UnsupportedFormatting(path.to_string()));
+    }
+    let source = std::fs::read_to_string(path)?;
+    let formatted = format_source(&source)?;
+    if formatted == source {
+        println!("{} is already formatted", path.bold());
+    } else if check {
+        trace!("format_file: exiting with error");
+        bail!(BraiseError::Unformatted(path.to_string()));
+    } else {
+        std::fs::write(path, formatted)?;
+        println!("Formatted {}", path.bold());
+    }
+    trace!("format_file: exiting");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_source;
+
+    #[test]
+    fn aliases_are_renamed_and_fields_ordered() {
+        let source = "\
+[build]
+deps = [\"lint\"]
+desc = \"Build it\"
+cmd = \"cargo build\"
+
+[lint]
+sh = \"bash -c\"
+command = \"cargo clippy\"
+";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+[build]
+command = \"cargo build\"
+description = \"Build it\"
+dependencies = [\"lint\"]
+
+[lint]
+command = \"cargo clippy\"
+shell = \"bash -c\"
+"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "\
+# The tasks
+[build] # Compile
+# Before compiling
+deps = [\"lint\"] # lint first
+cmd = \"cargo build\" # in debug
+unknown = 1
+";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+# The tasks
+[build] # Compile
+command = \"cargo build\" # in debug
+# Before compiling
+dependencies = [\"lint\"] # lint first
+unknown = 1
+"
+        );
+    }
+
+    #[test]
+    fn task_arrays_and_inline_tables_are_formatted() {
+        let source = "\
+deploy = { desc = \"Ship it\", cmd = \"./deploy.sh\" }
+
+[[test]]
+os = [\"linux\"]
+cmd = \"make test\"
+
+[[test]]
+cmd = \"nmake test\"
+args = [{ default = \"all\", name = \"suite\" }]
+";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+deploy = { command = \"./deploy.sh\", description = \"Ship it\" }
+
+[[test]]
+command = \"make test\"
+runs_on = [\"linux\"]
+
+[[test]]
+command = \"nmake test\"
+args = [{ name = \"suite\", default = \"all\" }]
+"
+        );
+    }
+
+    #[test]
+    fn settings_are_ordered_but_not_renamed() {
+        let source = "\
+[braise]
+default = \"build\"
+shell = \"bash -c\"
+env = { desc = \"kept\" }
+
+[build]
+cmd = \"cargo build\"
+";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "\
+[braise]
+shell = \"bash -c\"
+default = \"build\"
+env = { desc = \"kept\" }
+
+[build]
+command = \"cargo build\"
+"
+        );
+    }
+
+    #[test]
+    fn an_alias_is_kept_when_its_field_is_set() {
+        let source = "[build]\ncommand = \"a\"\ncmd = \"b\"\n";
+        assert_eq!(format_source(source).unwrap(), source);
+    }
+}
@@ -0,0 +1,348 @@
+use std::path::Path;
+
+use color_eyre::eyre::{bail, Context, Result};
+use lazy_static::lazy_static;
+use log::{debug, trace};
+use regex::Regex;
+
+use crate::{
+    error::BraiseError,
+    task::{BraiseArg, BraiseTask},
+};
+
+lazy_static! {
+    /// A Make rule, e.g. `build test: deps ## description`, but not `X := value`
+    pub(crate) static ref MAKE_RULE_REGEX: Regex =
+        Regex::new(r"^([A-Za-z0-9_./%-][A-Za-z0-9_./% \t-]*?)\s*::?(?:\s|$)(.*)$").unwrap();
+    /// A just recipe, e.g. `@deploy env="staging": build`, but not `alias b := build`
+    pub(crate) static ref JUST_RECIPE_REGEX: Regex =
+        Regex::new(r"^@?([A-Za-z_][A-Za-z0-9_-]*)((?:\s+[^:]*)?)\s*:(?:[^=]|$)").unwrap();
+    /// A parameter of a just recipe, e.g. `env="staging"` or `+files`
+    pub(crate) static ref JUST_PARAM_REGEX: Regex =
+        Regex::new(r#"[+*]?\$?([A-Za-z_][A-Za-z0-9_-]*)(?:=("[^"]*"|'[^']*'|\S+))?"#).unwrap();
+    /// An attribute of a just recipe, e.g. `private` or `doc("Build it")`
+    pub(crate) static ref JUST_ATTRIBUTE_REGEX: Regex =
+        Regex::new(r#"([a-z]+)(?:\(\s*(?:"([^"]*)"|'([^']*)')\s*\))?"#).unwrap();
+}
+
+/// The kinds of files tasks can be imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImportKind {
+    Npm,
+    Make,
+    Just,
+}
+
+impl ImportKind {
+    pub(crate) fn from_path(path: &str) -> Option<Self> {
+        let name = Path::new(path).file_name()?.to_str()?.to_lowercase();
+        if name == "package.json" {
+            Some(Self::Npm)
+        } else if name.contains("justfile") {
+            Some(Self::Just)
+        } else if name == "makefile" || name == "gnumakefile" || name.ends_with(".mk") {
+            Some(Self::Make)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read the tasks defined by a `package.json`, Makefile or justfile, relative to `dir`
+pub fn import_tasks(dir: &Path, path: &str) -> Result<Vec<(String, BraiseTask)>> {
+    trace!("import_tasks: entering");
+    let Some(kind) = ImportKind::from_path(path) else {
+        bail!(BraiseError::UnsupportedImport(path.to_string()));
+    };
+    let source = std::fs::read_to_string(dir.join(path))
+        .with_context(|| format!("Couldn't read {}", path))?;
+    let parent = Path::new(path)
+        .parent()
+        .map(|parent| parent.display().to_string())
+        .unwrap_or_default();
+    let file_name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut tasks = match kind {
+        ImportKind::Npm => npm_scripts(path, &source)?
+            .into_iter()
+            .map(|(name, mut task)| {
+                task.command = Some(if parent.is_empty() {
+                    format!("npm run {} --", name)
+                } else {
+                    format!("npm --prefix {} run {} --", parent, name)
+                });
+                (name, task)
+            })
+            .collect::<Vec<_>>(),
+        ImportKind::Make => make_targets(&source)
+            .into_iter()
+            .map(|(name, mut task)| {
+                task.command = Some(if parent.is_empty() && file_name == "Makefile" {
+                    format!("make {}", name)
+                } else if parent.is_empty() {
+                    format!("make -f {} {}", file_name, name)
+                } else {
+                    format!("make -C {} -f {} {}", parent, file_name, name)
+                });
+                (name, task)
+            })
+            .collect(),
+        ImportKind::Just => just_recipes(&source)
+            .into_iter()
+            .map(|(name, mut task)| {
+                task.command = Some(if parent.is_empty() && file_name == "justfile" {
+                    format!("just {}", name)
+                } else {
+                    format!("just --justfile {} {}", path, name)
+                });
+                (name, task)
+            })
+            .collect(),
+    };
+    for (_, task) in &mut tasks {
+        task.origin = Some(path.to_string());
+    }
+    debug!("Imported {} tasks from {}", tasks.len(), path);
+    trace!("import_tasks: exiting");
+    Ok(tasks)
+}
+
+/// The `scripts` of a `package.json`, skipping the `pre`/`post` hooks npm runs by itself
+fn npm_scripts(path: &str, source: &str) -> Result<Vec<(String, BraiseTask)>> {
+    let package = serde_json::from_str::<serde_json::Value>(source)
+        .map_err(|err| BraiseError::InvalidSyntax("JSON", path.to_string(), err.to_string()))?;
+    let Some(scripts) = package
+        .get("scripts")
+        .and_then(|scripts| scripts.as_object())
+    else {
+        return Ok(vec![]);
+    };
+    let is_hook = |name: &str| {
+        ["pre", "post"].iter().any(|prefix| {
+            name.strip_prefix(prefix)
+                .is_some_and(|script| scripts.contains_key(script))
+        })
+    };
+    Ok(scripts
+        .iter()
+        .filter(|(name, _)| !is_hook(name))
+        .map(|(name, script)| {
+            let task = BraiseTask {
+                description: script.as_str().map(|script| script.to_string()),
+                ..Default::default()
+            };
+            (name.to_string(), task)
+        })
+        .collect())
+}
+
+/// The explicit targets of a Makefile, described by the comment above them or a trailing
+/// `## comment`
+fn make_targets(source: &str) -> Vec<(String, BraiseTask)> {
+    let mut tasks: Vec<(String, BraiseTask)> = vec![];
+    let mut comments: Vec<&str> = vec![];
+    for line in source.lines() {
+        if let Some(comment) = line.strip_prefix('#') {
+            comments.push(comment.trim_start_matches('#').trim());
+            continue;
+        }
+        let description = comments.join(" ");
+        comments.clear();
+        let Some(captures) = MAKE_RULE_REGEX.captures(line) else {
+            continue;
+        };
+        let (rest, comment) = captures[2].split_once('#').unwrap_or((&captures[2], ""));
+        // `target: VAR = value` sets a variable for the target instead of defining it
+        if rest.contains('=') {
+            continue;
+        }
+        let comment = comment.trim_start_matches('#').trim();
+        let description = if comment.is_empty() {
+            description
+        } else {
+            comment.to_string()
+        };
+        for target in captures[1].split_whitespace() {
+            // Special targets like `.PHONY` and pattern rules aren't tasks
+            if target.starts_with('.') || target.contains('%') {
+                continue;
+            }
+            if tasks.iter().any(|(name, _)| name == target) {
+                continue;
+            }
+            let task = BraiseTask {
+                description: (!description.is_empty()).then(|| description.clone()),
+                ..Default::default()
+            };
+            tasks.push((target.to_string(), task));
+        }
+    }
+    tasks
+}
+
+/// The recipes of a justfile with their parameters, documentation, group and visibility
+fn just_recipes(source: &str) -> Vec<(String, BraiseTask)> {
+    let mut tasks = vec![];
+    let mut comments: Vec<&str> = vec![];
+    let mut attributes: Vec<(String, Option<String>)> = vec![];
+    for line in source.lines() {
+        if let Some(comment) = line.strip_prefix('#') {
+            // Shebangs and `#!` comments aren't documentation
+            if !comment.starts_with('!') {
+                comments.push(comment.trim());
+            }
+            continue;
+        }
+        if let Some(inner) = line
+            .trim_end()
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            attributes.extend(JUST_ATTRIBUTE_REGEX.captures_iter(inner).map(|captures| {
+                (
+                    captures[1].to_string(),
+                    captures
+                        .get(2)
+                        .or(captures.get(3))
+                        .map(|value| value.as_str().to_string()),
+                )
+            }));
+            continue;
+        }
+        let description = comments.join(" ");
+        comments.clear();
+        let recipe_attributes = std::mem::take(&mut attributes);
+        let Some(captures) = JUST_RECIPE_REGEX.captures(line) else {
+            continue;
+        };
+        let attribute = |name: &str| {
+            recipe_attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+        };
+        let name = captures[1].to_string();
+        let args = JUST_PARAM_REGEX
+            .captures_iter(&captures[2])
+            .map(|param| BraiseArg {
+                name: param[1].to_string(),
+                description: None,
+                default: param.get(2).map(|default| {
+                    default
+                        .as_str()
+                        .trim_matches(|c| c == '"' || c == '\'')
+                        .to_string()
+                }),
+                choices: None,
+            })
+            .collect::<Vec<_>>();
+        let task = BraiseTask {
+            description: attribute("doc")
+                .and_then(|(_, doc)| doc.clone())
+                .or((!description.is_empty()).then_some(description)),
+            group: attribute("group").and_then(|(_, group)| group.clone()),
+            private: (name.starts_with('_') || attribute("private").is_some()).then_some(true),
+            args: (!args.is_empty()).then_some(args),
+            ..Default::default()
+        };
+        tasks.push((name, task));
+    }
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::file::BraiseFile;
+
+    /// A fresh directory for a test, holding `files`
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("braise-import-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn read(dir: &Path, source: &str) -> BraiseFile {
+        BraiseFile::from_value(toml::from_str(source).unwrap(), dir).unwrap()
+    }
+
+    const MAKEFILE: &str = "build: ## Build it\n\tcc main.c\n\nclean:\n\trm -f a.out\n";
+    const PACKAGE: &str = r#"{ "scripts": { "build": "tsc", "lint": "eslint ." } }"#;
+
+    #[test]
+    fn local_tasks_and_earlier_imports_win() {
+        let dir = project(
+            "collisions",
+            &[("Makefile", MAKEFILE), ("web/package.json", PACKAGE)],
+        );
+        let file = read(
+            &dir,
+            "import = [\"Makefile\", \"web/package.json\"]\n\n[clean]\ncommand = \"git clean -fdx\"\n",
+        );
+        let command = |name: &str| file.tasks[name][0].command.as_deref().unwrap();
+        assert_eq!(command("clean"), "git clean -fdx");
+        assert_eq!(file.tasks["clean"][0].origin, None);
+        assert_eq!(command("build"), "make build");
+        assert_eq!(file.tasks["build"][0].origin.as_deref(), Some("Makefile"));
+        assert_eq!(command("lint"), "npm --prefix web run lint --");
+        assert!(file.diagnostics.is_empty(), "{:?}", file.diagnostics);
+    }
+
+    #[test]
+    fn missing_files_are_reported() {
+        let dir = project("missing", &[("Makefile", MAKEFILE)]);
+        let file = read(&dir, "[braise]\nimport = [\"justfile\", \"Makefile\"]\n");
+        assert_eq!(file.diagnostics.len(), 1);
+        assert_eq!(file.diagnostics[0].keys, ["braise", "import", "0"]);
+        assert!(
+            file.diagnostics[0]
+                .message
+                .starts_with("Couldn't import justfile"),
+            "{}",
+            file.diagnostics[0].message
+        );
+        assert!(file.tasks.contains_key("build"));
+    }
+
+    #[test]
+    fn unsupported_files_are_reported() {
+        let dir = project("unsupported", &[]);
+        let file = read(&dir, "import = [\"build.gradle\"]\n");
+        assert_eq!(file.diagnostics.len(), 1);
+        assert_eq!(file.diagnostics[0].keys, ["import", "0"]);
+    }
+
+    #[test]
+    fn import_is_read_flat_or_from_the_settings_table() {
+        let dir = project("placement", &[("Makefile", MAKEFILE)]);
+        for source in [
+            "import = [\"Makefile\"]\n",
+            "[braise]\nimport = [\"Makefile\"]\n",
+        ] {
+            let file = read(&dir, source);
+            assert_eq!(
+                file.tasks.keys().collect::<Vec<_>>(),
+                ["build", "clean"],
+                "{}",
+                source
+            );
+            assert_eq!(
+                file.tasks["build"][0].description.as_deref(),
+                Some("Build it")
+            );
+        }
+        // With a settings table, a top-level `import` is an unknown key rather than a setting
+        let file = read(&dir, "import = [\"Makefile\"]\n\n[braise]\nquiet = true\n");
+        assert!(file.tasks.is_empty());
+        assert_eq!(file.diagnostics.len(), 1);
+        assert_eq!(file.diagnostics[0].keys, ["import"]);
+    }
+}
@@ -0,0 +1,360 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{bail, Result},
+    owo_colors::OwoColorize,
+};
+use dialoguer::{theme::ColorfulTheme, MultiSelect};
+use indexmap::IndexMap;
+use log::{debug, trace};
+
+use crate::{
+    constants::schema_url,
+    error::BraiseError,
+    file::{find_file_in, FileFormat},
+    picker::is_interactive,
+    task::BraiseTask,
+    utils::confirm_action,
+    workspace::cargo_packages,
+};
+
+/// The kinds of projects `--init` can generate starter tasks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Template {
+    /// A Cargo package or workspace, from its `Cargo.toml`
+    Cargo,
+    /// A Node.js project, from its `package.json`
+    Npm,
+    /// A Go module, from its `go.mod`
+    Go,
+    /// A Python project, from its `pyproject.toml`
+    Python,
+    /// A Docker image, from its `Dockerfile`
+    Docker,
+    /// A single example task
+    Blank,
+}
+
+/// A task of a template
+fn task(command: impl Into<String>, description: &str) -> BraiseTask {
+    BraiseTask {
+        command: Some(command.into()),
+        description: Some(description.to_string()),
+        ..Default::default()
+    }
+}
+
+impl Template {
+    /// The templates matching the files found in `dir`, in the order they are generated
+    pub fn detect(dir: &Path) -> Vec<Self> {
+        [
+            (Self::Cargo, "Cargo.toml"),
+            (Self::Npm, "package.json"),
+            (Self::Go, "go.mod"),
+            (Self::Python, "pyproject.toml"),
+            (Self::Docker, "Dockerfile"),
+        ]
+        .into_iter()
+        .filter(|(_, file)| dir.join(file).is_file())
+        .map(|(template, _)| template)
+        .collect()
+    }
+
+    /// The name of the template, as given to `--template`
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+
+    /// The starter tasks of the template for the project in `dir`
+    pub fn tasks(self, dir: &Path) -> Result<Vec<(String, BraiseTask)>> {
+        trace!("Template::tasks: entering");
+        let tasks = match self {
+            Self::Cargo => cargo_tasks(dir)?,
+            Self::Npm => npm_tasks(dir)?,
+            Self::Go => vec![
+                (
+                    "build".to_string(),
+                    task("go build ./...", "Build every package"),
+                ),
+                ("test".to_string(), task("go test ./...", "Run the tests")),
+                (
+                    "lint".to_string(),
+                    task("go vet ./...", "Report suspicious code"),
+                ),
+                ("fmt".to_string(), task("gofmt -w .", "Format the code")),
+                ("run".to_string(), task("go run .", "Run the main package")),
+            ],
+            Self::Python => python_tasks(dir)?,
+            Self::Docker => {
+                let image = dir
+                    .canonicalize()
+                    .ok()
+                    .and_then(|dir| Some(dir.file_name()?.to_string_lossy().to_lowercase()))
+                    .unwrap_or("app".to_string());
+                vec![
+                    (
+                        "docker-build".to_string(),
+                        task(format!("docker build -t {} .", image), "Build the image"),
+                    ),
+                    (
+                        "docker-run".to_string(),
+                        task(format!("docker run --rm -it {}", image), "Run the image"),
+                    ),
+                ]
+            }
+            Self::Blank => vec![(
+                "echo".to_string(),
+                task(
+                    "echo Hello, world!",
+                    "Prints 'Hello, world!' to the console",
+                ),
+            )],
+        };
+        trace!("Template::tasks: exiting");
+        Ok(tasks)
+    }
+}
+
+fn cargo_tasks(dir: &Path) -> Result<Vec<(String, BraiseTask)>> {
+    let packages = cargo_packages(dir)?;
+    let manifest = cargo_toml::Manifest::from_path(dir.join("Cargo.toml"))?;
+    let flag = if manifest.workspace.is_some() {
+        " --workspace"
+    } else {
+        ""
+    };
+    let mut tasks = vec![
+        (
+            "build".to_string(),
+            task(format!("cargo build{}", flag), "Build the project"),
+        ),
+        (
+            "test".to_string(),
+            task(format!("cargo test{}", flag), "Run the tests"),
+        ),
+        (
+            "lint".to_string(),
+            task(
+                format!("cargo clippy{} --all-targets -- -D warnings", flag),
+                "Lint the code with clippy",
+            ),
+        ),
+        (
+            "fmt".to_string(),
+            task("cargo fmt --all", "Format the code"),
+        ),
+    ];
+    let runnable = packages
+        .iter()
+        .filter(|package| !package.bins.is_empty())
+        .collect::<Vec<_>>();
+    // A lone binary is simply run, those of a workspace are run by package
+    if packages.len() == 1 && runnable.len() == 1 {
+        tasks.push(("run".to_string(), task("cargo run", "Run the binary")));
+    } else {
+        for package in runnable {
+            tasks.push((
+                format!("run-{}", package.name),
+                task(
+                    format!("cargo run -p {}", package.name),
+                    &format!("Run {}", package.name),
+                ),
+            ));
+        }
+    }
+    Ok(tasks)
+}
+
+fn npm_tasks(dir: &Path) -> Result<Vec<(String, BraiseTask)>> {
+    let source = std::fs::read_to_string(dir.join("package.json"))?;
+    let package = serde_json::from_str::<serde_json::Value>(&source)?;
+    let scripts = package
+        .get("scripts")
+        .and_then(|scripts| scripts.as_object())
+        .cloned()
+        .unwrap_or_default();
+    let manager = [
+        ("pnpm-lock.yaml", "pnpm"),
+        ("yarn.lock", "yarn"),
+        ("bun.lockb", "bun"),
+        ("bun.lock", "bun"),
+    ]
+    .into_iter()
+    .find(|(lockfile, _)| dir.join(lockfile).is_file())
+    .map_or("npm", |(_, manager)| manager);
+    let mut tasks = vec![(
+        "install".to_string(),
+        task(format!("{} install", manager), "Install the dependencies"),
+    )];
+    // The scripts a project usually has, under the name of the task running them
+    for (name, scripts_names, description) in [
+        ("build", &["build"][..], "Build the project"),
+        ("test", &["test"], "Run the tests"),
+        ("lint", &["lint"], "Lint the code"),
+        ("fmt", &["format", "fmt"], "Format the code"),
+        ("run", &["dev", "start"], "Run the project"),
+    ] {
+        if let Some(script) = scripts_names
+            .iter()
+            .find(|script| scripts.contains_key(**script))
+        {
+            tasks.push((
+                name.to_string(),
+                task(format!("{} run {}", manager, script), description),
+            ));
+        }
+    }
+    Ok(tasks)
+}
+
+fn python_tasks(dir: &Path) -> Result<Vec<(String, BraiseTask)>> {
+    let source = std::fs::read_to_string(dir.join("pyproject.toml"))?;
+    let project = toml::from_str::<toml::Table>(&source)?;
+    let is_poetry = project
+        .get("tool")
+        .and_then(|tool| tool.get("poetry"))
+        .is_some();
+    let (runner, build) = if dir.join("uv.lock").is_file() {
+        ("uv run ", "uv build")
+    } else if is_poetry {
+        ("poetry run ", "poetry build")
+    } else {
+        ("", "python -m build")
+    };
+    let mut tasks = vec![
+        ("build".to_string(), task(build, "Build the package")),
+        (
+            "test".to_string(),
+            task(format!("{}pytest", runner), "Run the tests"),
+        ),
+        (
+            "lint".to_string(),
+            task(format!("{}ruff check .", runner), "Lint the code"),
+        ),
+        (
+            "fmt".to_string(),
+            task(format!("{}ruff format .", runner), "Format the code"),
+        ),
+    ];
+    let scripts = project
+        .get("project")
+        .and_then(|project| project.get("scripts"))
+        .and_then(|scripts| scripts.as_table());
+    if let Some(script) = scripts.and_then(|scripts| scripts.keys().next()) {
+        tasks.push((
+            "run".to_string(),
+            task(format!("{}{}", runner, script), "Run the project"),
+        ));
+    }
+    Ok(tasks)
+}
+
+/// Let the user choose among the detected templates, all of them being selected by default
+pub fn pick_templates(detected: &[Template]) -> Result<Vec<Template>> {
+    trace!("pick_templates: entering");
+    let names = detected
+        .iter()
+        .map(|template| template.name())
+        .collect::<Vec<_>>();
+    let picked = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Generate tasks for")
+        .items(&names)
+        .defaults(&vec![true; names.len()])
+        .interact()?;
+    trace!("pick_templates: exiting");
+    Ok(picked.into_iter().map(|index| detected[index]).collect())
+}
+
+/// Render a Braisefile with the starter tasks of `templates`. Tasks of later templates are
+/// prefixed with the name of their template when the name is already taken
+pub fn render_templates(dir: &Path, templates: &[Template]) -> Result<String> {
+    trace!("render_templates: entering");
+    let mut tasks = IndexMap::new();
+    for template in templates {
+        for (name, task) in template.tasks(dir)? {
+            let name = if tasks.contains_key(&name) {
+                format!("{}-{}", template.name(), name)
+            } else {
+                name
+            };
+            tasks.insert(name, task);
+        }
+    }
+    debug!("Generated {} tasks", tasks.len());
+    let content = format!(
+        "#:schema {}\n\n{}",
+        schema_url(),
+        toml::to_string_pretty(&tasks)?
+    );
+    trace!("render_templates: exiting");
+    Ok(content)
+}
+
+/// Write a Braisefile with starter tasks for the project in `dir`, from `template` or the
+/// templates detected in it. An existing Braisefile of `dir` is only replaced with `force` or
+/// once confirmed, and nothing is asked when not attached to a terminal or with `yes`
+pub fn init_file(dir: &Path, template: Option<Template>, yes: bool, force: bool) -> Result<()> {
+    trace!("init_file: entering");
+    if !dir.is_dir() {
+        bail!(BraiseError::NotADirectory(dir.display().to_string()));
+    }
+    let interactive = !yes && is_interactive();
+    // An existing TOML Braisefile is replaced in place, otherwise it would still be found first
+    let path = match find_file_in(dir) {
+        Ok(existing) => {
+            if !force {
+                if !interactive {
+                    trace!("init_file: exiting with error");
+                    bail!(BraiseError::FileExists(existing));
+                }
+                let prompt = format!(
+                    "The Braisefile already exists at {}, do you want to overwrite it? [y/{}]",
+                    existing.bold(),
+                    "N".bold()
+                );
+                if !confirm_action(&prompt)? {
+                    trace!("init_file: exiting");
+                    return Ok(());
+                }
+            }
+            if FileFormat::from_path(&existing) == FileFormat::Toml {
+                existing.into()
+            } else {
+                dir.join("braise.toml")
+            }
+        }
+        Err(_) => dir.join("braise.toml"),
+    };
+    let templates = match template {
+        Some(template) => vec![template],
+        None => {
+            let detected = Template::detect(dir);
+            debug!("Detected templates: {:?}", detected);
+            if detected.len() > 1 && interactive {
+                pick_templates(&detected)?
+            } else {
+                detected
+            }
+        }
+    };
+    let templates = if templates.is_empty() {
+        vec![Template::Blank]
+    } else {
+        templates
+    };
+    std::fs::write(&path, render_templates(dir, &templates)?)?;
+    println!(
+        "Initialized the Braisefile at {} ({})",
+        path.display().bold(),
+        templates
+            .iter()
+            .map(|template| template.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    trace!("init_file: exiting");
+    Ok(())
+}
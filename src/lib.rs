@@ -1,5 +1,18 @@
+pub mod affected;
+pub mod check;
+pub mod completions;
+pub mod condition;
 pub mod constants;
+pub mod docs;
 pub mod error;
 pub mod file;
+pub mod fmt;
+pub mod import;
+pub mod init;
+pub mod lsp;
+pub mod migrate;
+pub mod picker;
+pub mod schema;
 pub mod task;
 pub mod utils;
+pub mod workspace;
@@ -0,0 +1,603 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    process::Stdio,
+    thread::spawn,
+};
+
+use color_eyre::eyre::Result;
+use crossbeam_channel::Sender;
+use lazy_static::lazy_static;
+use log::{debug, trace};
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics, ShowMessage,
+    },
+    request::{
+        CodeLensRequest, Completion, ExecuteCommand, GotoDefinition, HoverRequest, Request as _,
+    },
+    CodeLens, CodeLensOptions, CodeLensParams, Command, CompletionItem, CompletionItemKind,
+    CompletionOptions, CompletionParams, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, ExecuteCommandOptions,
+    ExecuteCommandParams, GotoDefinitionParams, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, MessageType, Position,
+    PublishDiagnosticsParams, ServerCapabilities, ShowMessageParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use regex::Regex;
+use toml_edit::ImDocument;
+
+use crate::{
+    check::locate,
+    constants::TASK_FIELD_ALIASES,
+    file::{BraiseFile, FileFormat},
+};
+
+/// The command run by the "Run task" code lenses, with the task name and the file path as
+/// arguments
+pub const RUN_TASK_COMMAND: &str = "braise.runTask";
+
+lazy_static! {
+    /// The last key assigned on a line, e.g. `deps = [` in a task table
+    static ref KEY_REGEX: Regex = Regex::new(r#"(?m)^\s*([A-Za-z0-9_-]+)\s*="#).unwrap();
+}
+
+/// Serve the language server protocol over stdio until the client shuts us down
+pub fn run_server() -> Result<()> {
+    trace!("run_server: entering");
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["\"".to_string(), "'".to_string()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(lsp_types::OneOf::Left(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![RUN_TASK_COMMAND.to_string()],
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    debug!("Language server initialized");
+
+    let mut documents: HashMap<Url, String> = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = handle_request(request, &documents, &connection.sender)?;
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                let uri = match notification.method.as_str() {
+                    DidOpenTextDocument::METHOD => {
+                        let params: DidOpenTextDocumentParams =
+                            serde_json::from_value(notification.params)?;
+                        documents
+                            .insert(params.text_document.uri.clone(), params.text_document.text);
+                        params.text_document.uri
+                    }
+                    DidChangeTextDocument::METHOD => {
+                        let mut params: DidChangeTextDocumentParams =
+                            serde_json::from_value(notification.params)?;
+                        // We only ask for full syncs, so the last change is the whole document
+                        let Some(change) = params.content_changes.pop() else {
+                            continue;
+                        };
+                        documents.insert(params.text_document.uri.clone(), change.text);
+                        params.text_document.uri
+                    }
+                    DidCloseTextDocument::METHOD => {
+                        let params: DidCloseTextDocumentParams =
+                            serde_json::from_value(notification.params)?;
+                        documents.remove(&params.text_document.uri);
+                        publish_diagnostics(&connection.sender, params.text_document.uri, vec![])?;
+                        continue;
+                    }
+                    method => {
+                        debug!("Ignoring notification: {}", method);
+                        continue;
+                    }
+                };
+                let (_, diagnostics) = analyze(&documents[&uri], &document_path(&uri));
+                publish_diagnostics(&connection.sender, uri, diagnostics)?;
+            }
+            Message::Response(_) => {}
+        }
+    }
+    // The writer thread only stops once every sender is dropped
+    drop(connection);
+    io_threads.join()?;
+    trace!("run_server: exiting");
+    Ok(())
+}
+
+fn handle_request(
+    request: Request,
+    documents: &HashMap<Url, String>,
+    sender: &Sender<Message>,
+) -> Result<Response> {
+    debug!("Handling request: {}", request.method);
+    let id = request.id.clone();
+    let result = match request.method.as_str() {
+        Completion::METHOD => {
+            let params: CompletionParams = serde_json::from_value(request.params)?;
+            let position = params.text_document_position;
+            let uri = position.text_document.uri;
+            serde_json::to_value(documents.get(&uri).and_then(|source| {
+                completions(
+                    source,
+                    &document_path(&uri),
+                    offset(source, position.position),
+                )
+            }))?
+        }
+        HoverRequest::METHOD => {
+            let params: HoverParams = serde_json::from_value(request.params)?;
+            let position = params.text_document_position_params;
+            let uri = position.text_document.uri;
+            serde_json::to_value(documents.get(&uri).and_then(|source| {
+                hover(
+                    source,
+                    &document_path(&uri),
+                    offset(source, position.position),
+                )
+            }))?
+        }
+        GotoDefinition::METHOD => {
+            let params: GotoDefinitionParams = serde_json::from_value(request.params)?;
+            let position = params.text_document_position_params;
+            let uri = position.text_document.uri;
+            serde_json::to_value(documents.get(&uri).and_then(|source| {
+                let range = definition(
+                    source,
+                    &document_path(&uri),
+                    offset(source, position.position),
+                )?;
+                Some(Location::new(uri.clone(), range))
+            }))?
+        }
+        CodeLensRequest::METHOD => {
+            let params: CodeLensParams = serde_json::from_value(request.params)?;
+            let uri = params.text_document.uri;
+            serde_json::to_value(
+                documents
+                    .get(&uri)
+                    .map(|source| code_lenses(source, &uri))
+                    .unwrap_or_default(),
+            )?
+        }
+        ExecuteCommand::METHOD => {
+            let params: ExecuteCommandParams = serde_json::from_value(request.params)?;
+            if params.command == RUN_TASK_COMMAND {
+                run_task_command(params.arguments, sender.clone());
+            }
+            serde_json::Value::Null
+        }
+        method => {
+            return Ok(Response::new_err(
+                id,
+                lsp_server::ErrorCode::MethodNotFound as i32,
+                format!("Unhandled method {}", method),
+            ))
+        }
+    };
+    Ok(Response::new_ok(id, result))
+}
+
+fn publish_diagnostics(
+    sender: &Sender<Message>,
+    uri: Url,
+    diagnostics: Vec<lsp_types::Diagnostic>,
+) -> Result<()> {
+    let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+    sender.send(Message::Notification(Notification::new(
+        PublishDiagnostics::METHOD.to_string(),
+        params,
+    )))?;
+    Ok(())
+}
+
+/// The path of a document, whose extension gives its format and whose directory the files it
+/// imports are relative to
+fn document_path(uri: &Url) -> PathBuf {
+    uri.to_file_path().unwrap_or_default()
+}
+
+/// Parse a document, turning every problem found into a diagnostic
+fn analyze(source: &str, path: &Path) -> (Option<BraiseFile>, Vec<lsp_types::Diagnostic>) {
+    let error = |range: Range<usize>, message: String| {
+        lsp_types::Diagnostic::new(
+            lsp_types::Range::new(position(source, range.start), position(source, range.end)),
+            Some(DiagnosticSeverity::ERROR),
+            None,
+            Some(env!("CARGO_PKG_NAME").to_string()),
+            message,
+            None,
+            None,
+        )
+    };
+    let display = path.display().to_string();
+    let value = match FileFormat::from_path(&display) {
+        // TOML errors point at where they are
+        FileFormat::Toml => toml::from_str::<toml::Value>(source).map_err(|err| {
+            let message = err.message().to_string();
+            error(err.span().unwrap_or_default(), message)
+        }),
+        format => format
+            .parse(&display, source)
+            .map_err(|err| error(0..0, err.to_string())),
+    };
+    let value = match value {
+        Ok(value) => value,
+        Err(diagnostic) => return (None, vec![diagnostic]),
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let file = match BraiseFile::from_value(value, dir) {
+        Ok(file) => file,
+        Err(err) => return (None, vec![error(0..0, err.to_string())]),
+    };
+    let document = ImDocument::parse(source).ok();
+    let diagnostics = file
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let range = document
+                .as_ref()
+                .and_then(|document| locate(document, &diagnostic.keys))
+                .unwrap_or_default();
+            error(range, diagnostic.message.clone())
+        })
+        .collect();
+    (Some(file), diagnostics)
+}
+
+/// Complete task names and aliases inside a `dependencies` array
+fn completions(source: &str, path: &Path, offset: usize) -> Option<Vec<CompletionItem>> {
+    if !in_dependencies(&source[..offset]) {
+        return None;
+    }
+    // The array is usually unfinished while being typed, so try closing it
+    let file = ["", "\"]", "]"].iter().find_map(|suffix| {
+        let mut patched = source.to_string();
+        patched.insert_str(offset, suffix);
+        analyze(&patched, path).0
+    })?;
+    let tasks = file.all_tasks().map(|(name, variants)| CompletionItem {
+        label: name.to_string(),
+        kind: Some(CompletionItemKind::FUNCTION),
+        detail: variants
+            .iter()
+            .find_map(|variant| variant.description.clone()),
+        ..Default::default()
+    });
+    let aliases = file.aliases.iter().map(|(alias, name)| CompletionItem {
+        label: alias.to_string(),
+        kind: Some(CompletionItemKind::REFERENCE),
+        detail: Some(format!("Alias of {}", name)),
+        ..Default::default()
+    });
+    Some(tasks.chain(aliases).collect())
+}
+
+/// Whether the end of `before` is inside the array of a `dependencies` field
+fn in_dependencies(before: &str) -> bool {
+    let Some(captures) = KEY_REGEX.captures_iter(before).last() else {
+        return false;
+    };
+    let key = &captures[1];
+    let is_dependencies = TASK_FIELD_ALIASES
+        .iter()
+        .find(|(field, _)| *field == "dependencies")
+        .is_some_and(|(field, aliases)| *field == key || aliases.contains(&key));
+    let value = &before[captures.get(0).unwrap().end()..];
+    is_dependencies && value.matches('[').count() > value.matches(']').count()
+}
+
+/// The task named by the string or table header under the cursor
+fn task_at(source: &str, path: &Path, offset: usize) -> Option<(BraiseFile, String)> {
+    let is_delimiter = |c: char| c.is_whitespace() || "\"'[],={}#".contains(c);
+    let start = source[..offset]
+        .rfind(is_delimiter)
+        .map_or(0, |index| index + 1);
+    let end = source[offset..]
+        .find(is_delimiter)
+        .map_or(source.len(), |index| offset + index);
+    let token = &source[start..end];
+    let quoted = source[..start].ends_with(['"', '\'']);
+    let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+    let header = source[line_start..start].trim().chars().all(|c| c == '[');
+    if token.is_empty() || !(quoted || header) {
+        return None;
+    }
+    let file = analyze(source, path).0?;
+    let name = file.resolve(token)?.to_string();
+    Some((file, name))
+}
+
+fn hover(source: &str, path: &Path, offset: usize) -> Option<Hover> {
+    let (file, name) = task_at(source, path, offset)?;
+    let mut blocks = vec![format!("**{}**", name)];
+    // The task can be synthesized from the Cargo project rather than defined in the file
+    let variants = file.variants(&name)?;
+    if let Some(description) = variants
+        .iter()
+        .find_map(|variant| variant.description.as_deref())
+    {
+        blocks.push(description.to_string());
+    }
+    for variant in variants {
+        let command = if variant.workspace.unwrap_or(false) {
+            "(cargo workspace binary)"
+        } else if let Some(ref command) = variant.command {
+            command.trim_end()
+        } else {
+            continue;
+        };
+        if let Some(platforms) = variant.runs_on.as_ref().filter(|_| variants.len() > 1) {
+            blocks.push(format!("On {}:", platforms.join(", ")));
+        }
+        blocks.push(format!("```sh\n{}\n```", command));
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: blocks.join("\n\n"),
+        }),
+        range: None,
+    })
+}
+
+fn definition(source: &str, path: &Path, offset: usize) -> Option<lsp_types::Range> {
+    let (_, name) = task_at(source, path, offset)?;
+    let document = ImDocument::parse(source).ok()?;
+    let span = locate(&document, &[name])?;
+    Some(lsp_types::Range::new(
+        position(source, span.start),
+        position(source, span.end),
+    ))
+}
+
+/// A "Run task" lens above every task that can be run from the command line
+fn code_lenses(source: &str, uri: &Url) -> Vec<CodeLens> {
+    let path = document_path(uri);
+    let (Some(file), Ok(document)) = (analyze(source, &path).0, ImDocument::parse(source)) else {
+        return vec![];
+    };
+    let path = path.display().to_string();
+    file.tasks
+        .iter()
+        .filter(|(name, variants)| !variants.iter().all(|variant| variant.is_private(name)))
+        .filter_map(|(name, _)| {
+            let span = locate(&document, &[name.to_string()])?;
+            let position = position(source, span.start);
+            Some(CodeLens {
+                range: lsp_types::Range::new(position, position),
+                command: Some(Command::new(
+                    format!("▶ Run {}", name),
+                    RUN_TASK_COMMAND.to_string(),
+                    Some(vec![name.as_str().into(), path.as_str().into()]),
+                )),
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Run a task of the file at `path` from its directory, reporting the outcome to the client
+fn run_task_command(arguments: Vec<serde_json::Value>, sender: Sender<Message>) {
+    let [Some(name), Some(path)] = [0, 1].map(|index| {
+        arguments
+            .get(index)
+            .and_then(|argument| argument.as_str())
+            .map(|argument| argument.to_string())
+    }) else {
+        debug!(
+            "Invalid arguments for {}: {:?}",
+            RUN_TASK_COMMAND, arguments
+        );
+        return;
+    };
+    spawn(move || {
+        let dir = Path::new(&path).parent().unwrap_or(Path::new("."));
+        let output = std::env::current_exe().and_then(|exe| {
+            // The file is given explicitly, so that `BRAISE_FILE` or another Braisefile of the
+            // directory can't be picked instead
+            std::process::Command::new(exe)
+                .arg("--file")
+                .arg(&path)
+                .arg(&name)
+                .current_dir(dir)
+                .stdin(Stdio::null())
+                .output()
+        });
+        let (typ, message) = match output {
+            Ok(output) if output.status.success() => {
+                (MessageType::INFO, format!("Task {} succeeded", name))
+            }
+            Ok(output) => (
+                MessageType::ERROR,
+                format!(
+                    "Task {} failed: {}",
+                    name,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ),
+            Err(err) => (
+                MessageType::ERROR,
+                format!("Couldn't run task {}: {}", name, err),
+            ),
+        };
+        let notification = Notification::new(
+            ShowMessage::METHOD.to_string(),
+            ShowMessageParams { typ, message },
+        );
+        if sender.send(Message::Notification(notification)).is_err() {
+            debug!("Couldn't report the result of task {}", name);
+        }
+    });
+}
+
+/// The LSP position of a byte offset, with columns counted in UTF-16 code units
+fn position(source: &str, offset: usize) -> Position {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+/// The byte offset of an LSP position, clamped to the end of its line
+fn offset(source: &str, position: Position) -> usize {
+    let line_start = source
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum::<usize>();
+    let line = source[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use lsp_types::HoverContents;
+
+    use super::{definition, hover, task_at};
+
+    /// A fresh directory for a test, holding `files`
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("braise-lsp-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    /// The hover shown with the cursor right after `needle` in `source`
+    fn hover_at(source: &str, path: &Path, needle: &str) -> Option<String> {
+        let offset = source.find(needle).unwrap() + needle.len();
+        match hover(source, path, offset)?.contents {
+            HoverContents::Markup(markup) => Some(markup.value),
+            contents => panic!("unexpected hover {:?}", contents),
+        }
+    }
+
+    #[test]
+    fn hover_shows_synthesized_dependencies() {
+        let dir = project(
+            "hover-synthesized",
+            &[
+                ("Cargo.toml", "[package]\nname = \"p\"\n"),
+                ("src/lib.rs", ""),
+            ],
+        );
+        let source = "[check]\ndeps = [\"test:p\"]\n";
+        let hover = hover_at(source, &dir.join("braise.toml"), "\"test:").unwrap();
+        assert!(hover.starts_with("**test:p**"), "{}", hover);
+        assert!(hover.contains("cargo test -p p"), "{}", hover);
+    }
+
+    const SOURCE: &str = "\
+[build]
+cmd = \"cargo build\"
+aliases = [\"b\"]
+
+[ship]
+desc = \"Ship it\"
+deps = [\"b\", \"test:p\", \"nope\"]
+cmd = \"./ship.sh\"
+";
+
+    /// The task under the cursor right after `needle` in `SOURCE`
+    fn name_at(path: &Path, needle: &str) -> Option<String> {
+        let offset = SOURCE.find(needle).unwrap() + needle.len();
+        task_at(SOURCE, path, offset).map(|(_, name)| name)
+    }
+
+    /// The text a definition request right after `needle` in `SOURCE` points to
+    fn definition_at(path: &Path, needle: &str) -> Option<String> {
+        let offset = SOURCE.find(needle).unwrap() + needle.len();
+        let range = definition(SOURCE, path, offset)?;
+        let line = SOURCE.lines().nth(range.start.line as usize).unwrap();
+        assert_eq!(range.start.line, range.end.line);
+        Some(line[range.start.character as usize..range.end.character as usize].to_string())
+    }
+
+    #[test]
+    fn task_at_resolves_headers_dependencies_and_aliases() {
+        let path = Path::new("braise.toml");
+        assert_eq!(name_at(path, "[bui").as_deref(), Some("build"));
+        assert_eq!(name_at(path, "[\"b").as_deref(), Some("build"));
+        assert_eq!(name_at(path, "\"no"), None);
+        assert_eq!(name_at(path, "cmd = \"./sh"), None);
+        assert_eq!(name_at(path, "des"), None);
+    }
+
+    #[test]
+    fn task_at_resolves_synthesized_tasks() {
+        let dir = project(
+            "task-at-synthesized",
+            &[
+                ("Cargo.toml", "[package]\nname = \"p\"\n"),
+                ("src/lib.rs", ""),
+            ],
+        );
+        assert_eq!(
+            name_at(&dir.join("braise.toml"), "\"test:").as_deref(),
+            Some("test:p")
+        );
+        let dir = project("task-at-no-cargo", &[]);
+        assert_eq!(name_at(&dir.join("braise.toml"), "\"test:"), None);
+    }
+
+    #[test]
+    fn hover_shows_the_task_of_an_alias() {
+        let path = Path::new("braise.toml");
+        let hover = hover_at(SOURCE, path, "[\"b").unwrap();
+        assert_eq!(hover, "**build**\n\n```sh\ncargo build\n```");
+        let hover = hover_at(SOURCE, path, "[sh").unwrap();
+        assert!(hover.starts_with("**ship**\n\nShip it"), "{}", hover);
+        assert_eq!(hover_at(SOURCE, path, "\"no"), None);
+    }
+
+    #[test]
+    fn definition_goes_to_the_task_header() {
+        let dir = project(
+            "definition",
+            &[
+                ("Cargo.toml", "[package]\nname = \"p\"\n"),
+                ("src/lib.rs", ""),
+            ],
+        );
+        let path = dir.join("braise.toml");
+        assert_eq!(definition_at(&path, "[\"b").as_deref(), Some("build"));
+        assert_eq!(definition_at(&path, "[sh").as_deref(), Some("ship"));
+        // Synthesized tasks aren't defined anywhere in the file
+        assert_eq!(definition_at(&path, "\"test:"), None);
+        assert_eq!(definition_at(&path, "\"no"), None);
+    }
+}
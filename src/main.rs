@@ -8,13 +8,25 @@ use std::{
 };
 
 use braise::{
-    constants::TASKS_SEPARATOR,
+    affected::{affected_packages, affected_tasks, changed_files, default_base},
+    check::check_file,
+    completions::{print_completions, with_task_subcommands},
+    constants::{FILE_ENV_VAR, MIGRATE_SOURCES, TASKS_SEPARATOR},
+    docs::{render_docs, DocsFormat},
     error::BraiseError,
-    file::{find_file, print_tasks, BraiseFile},
-    task::run_task,
-    utils::{build_logger, confirm_action, init_panic, version},
+    file::{find_file, print_tasks, BraiseFile, ListFormat, ListSort},
+    fmt::format_file,
+    init::{init_file, Template},
+    lsp::run_server,
+    migrate::migrate,
+    picker::{is_interactive, pick_task},
+    schema::file_schema,
+    task::{report_skipped, run_task},
+    utils::{build_logger, confirm_action, init_panic, resolve_args, version},
+    workspace::cargo_packages,
 };
-use clap::{arg, Command};
+use clap::{arg, value_parser, ArgMatches, Command};
+use clap_complete::{env::Shells, CompleteEnv};
 use color_eyre::{
     eyre::{bail, eyre, Context, Result},
     owo_colors::OwoColorize,
@@ -22,20 +34,97 @@ use color_eyre::{
 use either::Either;
 use log::{debug, trace};
 
-fn main() -> Result<()> {
-    let mut logger = build_logger();
-
-    let matches = Command::new(env!("CARGO_PKG_NAME"))
+fn cli() -> Command {
+    Command::new(env!("CARGO_PKG_NAME"))
         .allow_external_subcommands(true)
         .version(version())
         .author(clap::crate_authors!())
         .about(env!("CARGO_PKG_DESCRIPTION"))
-        .arg(arg!(-i --init <PATH> "Initialize a sample Braise file with the JSON schema"))
-        .arg(arg!(-l --list "List all tasks"))
+        .after_help("Builtin commands (a task with the same name takes precedence):\n  fmt      Format the Braisefile\n  lsp      Start the language server over stdio\n  migrate  Convert a justfile, Makefile or package.json into a Braisefile")
+        .arg(
+            arg!(-f --file <PATH> "Use the Braise file at PATH, running its tasks from its directory")
+                .env(FILE_ENV_VAR),
+        )
+        .arg(arg!(-C --dir <DIR> "Change to DIR before doing anything"))
+        .arg(arg!(-i --init <PATH> "Initialize a Braise file with starter tasks for the project in PATH"))
+        .arg(
+            arg!(--template <NAME> "The project type to generate tasks for, detected by default")
+                .value_parser(value_parser!(Template))
+                .requires("init"),
+        )
+        .arg(arg!(-y --yes "Don't ask which templates to use").requires("init"))
+        .arg(arg!(--force "Overwrite the existing Braise file").requires("init"))
+        .arg(arg!(-l --list [PATTERN] "List all tasks, or those matching PATTERN"))
+        .arg(
+            arg!(--format <FORMAT> "The output format of --list")
+                .value_parser(value_parser!(ListFormat))
+                .default_value("plain")
+                .requires("list"),
+        )
+        .arg(
+            arg!(--sort <ORDER> "The order of the tasks in --list")
+                .value_parser(value_parser!(ListSort))
+                .default_value("file")
+                .requires("list"),
+        )
+        .arg(
+            arg!(--docs [FORMAT] "Print the documentation of the tasks")
+                .value_parser(value_parser!(DocsFormat))
+                .default_missing_value("markdown"),
+        )
+        .arg(arg!(--check "Report every problem found in the Braise file"))
+        .arg(arg!(--schema "Print the JSON schema of the Braise file"))
         .arg(arg!(-q --quiet... "Suppress all output"))
         .arg(arg!(-d --debug... "Print debug information"))
         .arg(arg!(-p --parallel "Run tasks in parallel"))
-        .get_matches();
+        .arg(
+            arg!(--affected [BASE_REF] "Only run the per-package tasks of the packages affected since BASE_REF")
+                .require_equals(true),
+        )
+        .arg(
+            arg!(--completions <SHELL> "Print the completion script for SHELL")
+                .value_parser(Shells::builtins().names().collect::<Vec<_>>()),
+        )
+}
+
+fn migrate_cli() -> Command {
+    Command::new("migrate")
+        .about("Convert a justfile, Makefile or package.json into a Braisefile")
+        .arg(arg!([SOURCE] "The file to convert, found in the current directory by default"))
+        .arg(
+            arg!(-o --output <PATH> "Where to write the Braisefile, - for stdout")
+                .default_value("braise.toml"),
+        )
+        .arg(arg!(--force "Overwrite the output file if it exists"))
+}
+
+fn fmt_cli() -> Command {
+    Command::new("fmt")
+        .about("Format the Braisefile")
+        .arg(arg!(--check "Fail if the Braisefile isn't formatted instead of formatting it"))
+}
+
+/// The arguments following an external subcommand
+fn external_args(matches: &ArgMatches) -> Result<Vec<String>> {
+    Ok(matches
+        .get_many::<OsString>("")
+        .ok_or(eyre!("Couldn't parse external args"))?
+        .map(|s| s.to_string_lossy().to_string())
+        .collect())
+}
+
+/// Whether the Braisefile defines a task (or alias) shadowing a builtin command
+fn defines_task(path: Option<&String>, name: &str) -> bool {
+    path.and_then(|path| BraiseFile::read(path).ok())
+        .is_some_and(|file| file.resolve(name).is_some())
+}
+
+fn main() -> Result<()> {
+    CompleteEnv::with_factory(|| with_task_subcommands(cli())).complete();
+
+    let mut logger = build_logger();
+
+    let matches = cli().get_matches();
 
     let debug_level = matches.get_count("debug");
     let quiet_level = matches.get_count("quiet");
@@ -51,67 +140,167 @@ fn main() -> Result<()> {
 
     debug!("Matches: {:#?}", matches);
 
-    if let Some(path) = matches.get_one::<String>("init") {
-        trace!("main: initializing");
-        let mut name = "braise.toml".to_string();
-        if let Ok(file) = find_file() {
-            println!("The Braisefile already exists at {}", file.bold());
-            // Ask if they want to overwrite
-            let mut input = String::new();
-            println!("Do you want to overwrite it? [y/{}]", "N".bold());
-            std::io::stdin().read_line(&mut input)?;
-            if input.trim().to_lowercase() != "y" {
-                println!("Exiting...");
-                return Ok(());
+    // Like make, `-C` comes first so that `-f` can be relative to it
+    if let Some(dir) = matches.get_one::<String>("dir") {
+        env::set_current_dir(dir).with_context(|| format!("Couldn't change to {}", dir.bold()))?;
+    }
+    // Only the commands reading the file fail when it is missing
+    let found = match matches.get_one::<String>("file") {
+        Some(file) if !Path::new(file).is_file() => {
+            Err(BraiseError::FileNotFound(file.to_string()).into())
+        }
+        Some(file) => Ok(file.to_string()),
+        None => find_file(),
+    };
+    debug!("Braise file: {:?}", found);
+
+    if let Some(shell) = matches.get_one::<String>("completions") {
+        trace!("main: printing completions");
+        print_completions(shell)?;
+        trace!("main: exiting from completions");
+        return Ok(());
+    }
+
+    if matches.get_flag("schema") {
+        trace!("main: printing schema");
+        println!("{}", serde_json::to_string_pretty(&file_schema())?);
+        trace!("main: exiting from schema");
+        return Ok(());
+    }
+
+    if matches.subcommand_name() == Some("lsp") && !defines_task(found.as_ref().ok(), "lsp") {
+        trace!("main: starting the language server");
+        run_server()?;
+        trace!("main: exiting from lsp");
+        return Ok(());
+    }
+
+    if let Some(("migrate", sub_matches)) = matches.subcommand() {
+        if !defines_task(found.as_ref().ok(), "migrate") {
+            trace!("main: migrating");
+            let args = external_args(sub_matches)?;
+            let migrate_matches =
+                migrate_cli().get_matches_from(std::iter::once("migrate".to_string()).chain(args));
+            let source = match migrate_matches.get_one::<String>("SOURCE") {
+                Some(source) => source.to_string(),
+                None => MIGRATE_SOURCES
+                    .iter()
+                    .find(|source| Path::new(source).exists())
+                    .ok_or(eyre!(
+                        "No file to migrate found, expected one of: {}",
+                        MIGRATE_SOURCES.join(", ")
+                    ))?
+                    .to_string(),
+            };
+            let output = migrate_matches
+                .get_one::<String>("output")
+                .expect("output has a default value");
+            let migration = migrate(&source)?;
+            for warning in &migration.warnings {
+                eprintln!("{}: {}", "warning".yellow().bold(), warning);
             }
-            name = file;
+            if output == "-" {
+                print!("{}", migration.content);
+            } else {
+                if Path::new(output).exists() && !migrate_matches.get_flag("force") {
+                    bail!(BraiseError::FileExists(output.to_string()));
+                }
+                std::fs::write(output, &migration.content)?;
+                eprintln!(
+                    "Migrated {} tasks from {} to {}",
+                    migration.tasks,
+                    source.bold(),
+                    output.bold()
+                );
+            }
+            trace!("main: exiting from migrate");
+            return Ok(());
         }
-        let content = format!(
-            r#"#:schema {}
-
-[echo]
-command = "echo Hello, world!"
-description = "Prints 'Hello, world!' to the console"
-"#,
-            braise::constants::SCHEMA_URL
-        );
-        let file_path = Path::new(&path);
-        let joined = file_path.join(name);
-        std::fs::write(&joined, content)?;
-        println!("Initialized the Braisefile at {}", joined.display().bold());
+    }
+
+    if let Some(("fmt", sub_matches)) = matches.subcommand() {
+        if !defines_task(found.as_ref().ok(), "fmt") {
+            trace!("main: formatting");
+            let args = external_args(sub_matches)?;
+            let fmt_matches =
+                fmt_cli().get_matches_from(std::iter::once("fmt".to_string()).chain(args));
+            format_file(&found?, fmt_matches.get_flag("check"))?;
+            trace!("main: exiting from fmt");
+            return Ok(());
+        }
+    }
+
+    if let Some(path) = matches.get_one::<String>("init") {
+        trace!("main: initializing");
+        init_file(
+            Path::new(path),
+            matches.get_one::<Template>("template").copied(),
+            matches.get_flag("yes"),
+            matches.get_flag("force"),
+        )?;
         trace!("main: exiting from init");
         return Ok(());
     }
-    let path = find_file()?;
+    let mut path = found?;
     debug!("Found file at: {}", path);
+    // Tasks run from the directory of the file given with `-f`, unless `-C` says otherwise
+    if matches.contains_id("file") && !matches.contains_id("dir") {
+        let file = Path::new(&path);
+        if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            env::set_current_dir(dir)
+                .with_context(|| format!("Couldn't change to {}", dir.display().bold()))?;
+        }
+        if let Some(name) = file.file_name() {
+            path = name.to_string_lossy().to_string();
+        }
+    }
 
-    let value = toml::from_str::<toml::Value>(&std::fs::read_to_string(path.clone())?)?;
-    debug!("Parsed file: {:#?}", value);
+    if matches.get_flag("check") {
+        trace!("main: checking file");
+        check_file(&path)?;
+        trace!("main: exiting from check");
+        return Ok(());
+    }
 
-    let file = Arc::new(BraiseFile::from_value(value)?);
-    debug!("Parsed braisé file: {:#?}", file);
+    let mut file = BraiseFile::from_path(&path)?;
 
-    if matches.get_flag("list") {
+    if matches.contains_id("list") {
         trace!("main: listing tasks");
-        print_tasks(&file, path);
+        let format = *matches
+            .get_one::<ListFormat>("format")
+            .expect("format has a default value");
+        let sort = *matches
+            .get_one::<ListSort>("sort")
+            .expect("sort has a default value");
+        print_tasks(
+            &file,
+            path,
+            format,
+            sort,
+            matches.get_one::<String>("list").map(|p| p.as_str()),
+        )?;
         trace!("main: exiting from list");
         return Ok(());
     }
 
-    let (input, args) = if let Some((input, matches)) = matches.subcommand() {
-        (
-            input.to_string(),
-            matches
-                .get_many::<OsString>("")
-                .ok_or(eyre!("Couldn't parse external args"))?
-                .collect::<Vec<_>>()
-                .into_iter()
-                .map(|s| s.to_string_lossy().to_string())
-                .collect::<Vec<_>>(),
-        )
+    if let Some(format) = matches.get_one::<DocsFormat>("docs") {
+        trace!("main: printing docs");
+        print!("{}", render_docs(&file, &path, *format));
+        trace!("main: exiting from docs");
+        return Ok(());
+    }
+
+    let (input, args, from_cli) = if let Some((input, matches)) = matches.subcommand() {
+        (input.to_string(), external_args(matches)?, true)
     } else {
-        if let Some(ref default) = file.default {
-            (default.to_string(), vec![])
+        if let Some(ref default) = file.settings.default {
+            (default.to_string(), vec![], false)
+        } else if is_interactive() {
+            trace!("main: picking a task");
+            match pick_task(&file)? {
+                Some((task, args)) => (task, args, true),
+                None => return Ok(()),
+            }
         } else {
             bail!(BraiseError::NoTask);
         }
@@ -123,8 +312,46 @@ description = "Prints 'Hello, world!' to the console"
         .split(TASKS_SEPARATOR)
         .map(|e| e.to_owned())
         .collect::<Vec<_>>();
+    let inputs = if matches.contains_id("affected") {
+        trace!("main: finding affected packages");
+        let base = matches
+            .get_one::<String>("affected")
+            .cloned()
+            .unwrap_or_else(default_base);
+        let packages = cargo_packages(file.dir())?;
+        let affected = affected_packages(&packages, &changed_files(file.dir(), &base)?);
+        if quiet_level == 0 {
+            let names = affected
+                .iter()
+                .map(|package| package.name.as_str())
+                .collect::<Vec<_>>();
+            println!(
+                "{}",
+                format!(
+                    "Affected packages since {}: {}",
+                    base,
+                    if names.is_empty() {
+                        "none".to_string()
+                    } else {
+                        names.join(", ")
+                    }
+                )
+                .dimmed()
+            );
+        }
+        file.packages = Some(
+            affected
+                .iter()
+                .map(|package| package.name.to_string())
+                .collect(),
+        );
+        affected_tasks(&file, inputs, &packages, &affected)
+    } else {
+        inputs
+    };
+    let file = Arc::new(file);
 
-    let parallel = matches.get_flag("parallel") || file.parallel.unwrap_or(false);
+    let parallel = matches.get_flag("parallel") || file.settings.parallel.unwrap_or(false);
     for task_name in inputs {
         let file = file.clone();
         let args = args.clone();
@@ -137,54 +364,18 @@ description = "Prints 'Hello, world!' to the console"
             }
         }
         let handle = spawn(move || {
-            let tasks = file
-                .tasks
-                .get(&task_name)
-                .ok_or(BraiseError::InvalidTask(task_name.to_string()))?;
-            let task = tasks
-                .iter()
-                .find(|task| {
-                    task.runs_on
-                        .as_ref()
-                        .map(|os| {
-                            os.iter()
-                                .any(|os| os.to_lowercase() == std::env::consts::OS.to_lowercase())
-                        })
-                        .unwrap_or(true)
-                })
-                .ok_or(BraiseError::TaskNotFound(task_name.to_string()))?;
-            debug!("Running task: {}", task_name);
-
-            match &task.confirm {
-                Some(confirm) => match confirm.0 {
-                    Either::Left(ref confirm) => {
-                        let prompt = if confirm.is_empty() {
-                            "Are you sure? [y/N]"
-                        } else {
-                            confirm
-                        };
-                        if !confirm_action(prompt)? {
-                            return Ok(());
-                        }
-                    }
-                    Either::Right(true) => {
-                        if !confirm_action("Are you sure? [y/N]")? {
-                            return Ok(());
-                        }
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
+            let task_name = file.resolve(&task_name).ok_or_else(|| {
+                BraiseError::UnknownTask(task_name.to_string(), file.suggest(&task_name, false))
+            })?;
 
-            let mut env_vars = match &file.dotenv {
-                Either::Left(Some(dotenv)) => {
+            let mut env_vars = match file.settings.dotenv.as_ref().map(|dotenv| &dotenv.0) {
+                Some(Either::Left(dotenv)) => {
                     debug!("Reading dotenv file: {}", dotenv);
                     dotenvy::from_filename_iter(dotenv)
                         .context(format!("Couldn't read dotenv file: {}", dotenv.bold()))?
                         .collect::<Vec<_>>()
                 }
-                Either::Right(Some(true)) => {
+                Some(Either::Right(true)) => {
                     debug!("Reading dotenv file: .env");
                     dotenvy::dotenv_iter()
                         .map(|res| res.collect::<Vec<_>>())
@@ -212,13 +403,47 @@ description = "Prints 'Hello, world!' to the console"
 
             debug!("Env vars: {:#?}", env_vars);
 
+            // The conditions of the task hold in the environment it runs in
+            let task = match file.pick_task(task_name, &env_vars)? {
+                Ok(task) => task,
+                Err(Some(condition)) => {
+                    report_skipped(quiet_level, &file, task_name, condition);
+                    return Ok(());
+                }
+                Err(None) => bail!(BraiseError::TaskNotFound(task_name.to_string())),
+            };
+            if from_cli && task.is_private(task_name) {
+                bail!(BraiseError::PrivateTask(task_name.to_string()));
+            }
+            let args = resolve_args(task, &args)?;
+            debug!("Running task: {}", task_name);
+
+            if let Some(confirm) = &task.confirm {
+                match confirm.0 {
+                    Either::Left(ref confirm) => {
+                        let prompt = if confirm.is_empty() {
+                            "Are you sure? [y/N]"
+                        } else {
+                            confirm
+                        };
+                        if !confirm_action(prompt)? {
+                            return Ok(());
+                        }
+                    }
+                    Either::Right(true) if !confirm_action("Are you sure? [y/N]")? => {
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+            }
+
             run_task(
                 quiet_level,
                 task,
                 &args,
                 &file,
                 &env_vars,
-                &task_name,
+                task_name,
                 vec![],
             )?;
             color_eyre::eyre::Ok(())
@@ -0,0 +1,999 @@
+use std::fmt::Display;
+
+use color_eyre::eyre::{bail, Context, Result};
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use log::{debug, trace};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    constants::schema_url,
+    error::BraiseError,
+    file::BraiseSettings,
+    import::{
+        ImportKind, JUST_ATTRIBUTE_REGEX, JUST_PARAM_REGEX, JUST_RECIPE_REGEX, MAKE_RULE_REGEX,
+    },
+    task::{BraiseArg, BraiseTask, StringOrBool},
+    utils::env_requirements,
+};
+
+lazy_static! {
+    /// A Make variable, e.g. `VERSION := 1.0` or `export CC ?= gcc`
+    static ref MAKE_VARIABLE_REGEX: Regex = Regex::new(
+        r"^(?:export\s+|override\s+)?([A-Za-z_][A-Za-z0-9_]*)\s*(:::=|::?=|\?=|\+=|!=|=)\s*(.*)$"
+    )
+    .unwrap();
+    /// A just variable, e.g. `export VERSION := "1.0"`
+    static ref JUST_VARIABLE_REGEX: Regex =
+        Regex::new(r"^(?:export\s+)?([A-Za-z_][A-Za-z0-9_-]*)\s*:=\s*(.*)$").unwrap();
+    /// A just alias, e.g. `alias b := build`
+    static ref JUST_ALIAS_REGEX: Regex =
+        Regex::new(r"^alias\s+([A-Za-z_][A-Za-z0-9_-]*)\s*:=\s*([A-Za-z_][A-Za-z0-9_-]*)\s*$")
+            .unwrap();
+    /// A just setting, e.g. `set shell := ["bash", "-c"]` or `set dotenv-load`
+    static ref JUST_SETTING_REGEX: Regex =
+        Regex::new(r"^set\s+([a-z-]+)(?:\s*:=\s*(.*))?$").unwrap();
+    /// An interpolation in a just recipe, e.g. `{{ version }}`
+    static ref JUST_INTERPOLATION_REGEX: Regex = Regex::new(r"\{\{\s*(.*?)\s*\}\}").unwrap();
+    /// A quoted string, e.g. `"bash"` or `'-c'`
+    static ref QUOTED_REGEX: Regex = Regex::new(r#""([^"]*)"|'([^']*)'"#).unwrap();
+    /// A dependency with arguments in a just recipe, e.g. `(build "release")`
+    static ref JUST_CALL_REGEX: Regex = Regex::new(r"\([^)]*\)").unwrap();
+}
+
+/// The Make directives that have no equivalent in a Braisefile
+const MAKE_DIRECTIVES: [&str; 13] = [
+    "include", "-include", "sinclude", "ifeq", "ifneq", "ifdef", "ifndef", "else", "endif",
+    "define", "endef", "unexport", "vpath",
+];
+
+/// A Braisefile converted from another task runner
+pub struct Migration {
+    /// The contents of the new Braisefile
+    pub content: String,
+    /// The number of tasks converted
+    pub tasks: usize,
+    /// What couldn't be translated, as `path:line: message`
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct MigratedFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    braise: Option<BraiseSettings>,
+    #[serde(flatten)]
+    tasks: IndexMap<String, BraiseTask>,
+}
+
+/// Convert a justfile, Makefile or `package.json` into a Braisefile
+pub fn migrate(path: &str) -> Result<Migration> {
+    trace!("migrate: entering");
+    let Some(kind) = ImportKind::from_path(path) else {
+        bail!(BraiseError::UnsupportedMigration(path.to_string()));
+    };
+    let source =
+        std::fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path))?;
+    let mut converter = Converter {
+        path,
+        settings: BraiseSettings::default(),
+        tasks: IndexMap::new(),
+        warnings: vec![],
+    };
+    match kind {
+        ImportKind::Npm => converter.npm(&source)?,
+        ImportKind::Make => converter.make(&source),
+        ImportKind::Just => converter.just(&source),
+    }
+    let Converter {
+        settings,
+        tasks,
+        warnings,
+        ..
+    } = converter;
+    let has_settings = settings.shell.is_some()
+        || settings.default.is_some()
+        || settings.dotenv.is_some()
+        || settings.env.is_some();
+    let count = tasks.len();
+    let file = MigratedFile {
+        braise: has_settings.then_some(settings),
+        tasks,
+    };
+    let content = format!(
+        "#:schema {}\n# Migrated from {} by `braise migrate`\n\n{}",
+        schema_url(),
+        path,
+        toml::to_string_pretty(&file)?
+    );
+    debug!("Migrated {} tasks with {} warnings", count, warnings.len());
+    trace!("migrate: exiting");
+    Ok(Migration {
+        content,
+        tasks: count,
+        warnings,
+    })
+}
+
+struct Converter<'a> {
+    path: &'a str,
+    settings: BraiseSettings,
+    tasks: IndexMap<String, BraiseTask>,
+    warnings: Vec<String>,
+}
+
+/// A just recipe, converted once every variable is known
+struct JustRecipe {
+    name: String,
+    line: usize,
+    description: Option<String>,
+    group: Option<String>,
+    private: bool,
+    /// The parameters, with their default value and whether they are variadic
+    params: Vec<(String, Option<String>, bool)>,
+    dependencies: String,
+    body: Vec<String>,
+    indent: Option<String>,
+}
+
+/// A Make rule, converted once every target is known
+struct MakeRule {
+    targets: Vec<String>,
+    line: usize,
+    description: Option<String>,
+    prerequisites: Vec<String>,
+    body: Vec<String>,
+}
+
+/// How Make variable references are translated
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MakeContext {
+    /// In a recipe, where variables are read from the environment by the shell
+    Recipe,
+    /// In the value of another variable, interpolated by braise
+    Value,
+}
+
+impl Converter<'_> {
+    fn warn(&mut self, line: Option<usize>, message: impl Display) {
+        self.warnings.push(match line {
+            Some(line) => format!("{}:{}: {}", self.path, line, message),
+            None => format!("{}: {}", self.path, message),
+        });
+    }
+
+    /// Join the lines of a recipe into one command. Make and just run each line in a shell of
+    /// its own and stop at the first one failing, so the lines are chained with `&&`, and a `cd`
+    /// now also applies to the lines after it
+    fn join_recipe(&mut self, line: Option<usize>, name: &str, commands: &[String]) -> String {
+        let last = commands.len().saturating_sub(1);
+        if commands[..last]
+            .iter()
+            .any(|command| command == "cd" || command.starts_with("cd "))
+        {
+            self.warn(
+                line,
+                format!("A `cd` in {} now applies to the lines after it", name),
+            );
+        }
+        commands.join(" && ")
+    }
+
+    fn set_env(&mut self, key: &str, value: String) {
+        self.settings
+            .env
+            .get_or_insert_with(IndexMap::new)
+            .insert(key.to_string(), value);
+    }
+
+    fn npm(&mut self, source: &str) -> Result<()> {
+        let package = serde_json::from_str::<serde_json::Value>(source).map_err(|err| {
+            BraiseError::InvalidSyntax("JSON", self.path.to_string(), err.to_string())
+        })?;
+        let Some(scripts) = package.get("scripts").and_then(|s| s.as_object()) else {
+            self.warn(None, "No scripts found");
+            return Ok(());
+        };
+        for (name, script) in scripts {
+            let Some(script) = script.as_str() else {
+                self.warn(None, format!("Script {} is not a string", name));
+                continue;
+            };
+            if script.contains("$npm_") {
+                self.warn(
+                    None,
+                    format!("Script {} uses npm's environment variables", name),
+                );
+            }
+            let task = BraiseTask {
+                command: Some(script.to_string()),
+                ..Default::default()
+            };
+            self.tasks.insert(name.to_string(), task);
+        }
+        // npm runs `preX` and `postX` around `X` by itself
+        for name in scripts.keys() {
+            if let Some(script) = name
+                .strip_prefix("pre")
+                .filter(|script| scripts.contains_key(*script))
+            {
+                self.tasks[script]
+                    .dependencies
+                    .get_or_insert_with(Vec::new)
+                    .push(name.to_string());
+            } else if let Some(script) = name
+                .strip_prefix("post")
+                .filter(|script| scripts.contains_key(*script))
+            {
+                self.warn(
+                    None,
+                    format!(
+                        "{} runs after {} with npm, run `braise {},{}` instead",
+                        name, script, script, name
+                    ),
+                );
+            }
+        }
+        // npm puts the binaries of the dependencies in the PATH of the scripts
+        self.set_env("PATH", "node_modules/.bin:{env(PATH)}".to_string());
+        Ok(())
+    }
+
+    fn make(&mut self, source: &str) {
+        // Join continued lines, `Some(true)` being a continued recipe line
+        let mut lines: Vec<(usize, String)> = vec![];
+        let mut continued = None;
+        for (index, line) in source.lines().enumerate() {
+            match continued {
+                // The shell drops the backslash and the newline, and Make the tab starting the
+                // next line, so that the lines of a recipe can be chained
+                Some(true) => {
+                    let (_, last) = lines.last_mut().expect("a continued line exists");
+                    last.pop();
+                    last.push_str(line.strip_prefix('\t').unwrap_or(line));
+                }
+                Some(false) => {
+                    let (_, last) = lines.last_mut().expect("a continued line exists");
+                    last.push(' ');
+                    last.push_str(line.trim().trim_end_matches('\\'));
+                }
+                None if line.starts_with('\t') => lines.push((index + 1, line.to_string())),
+                None => lines.push((index + 1, line.trim_end_matches('\\').to_string())),
+            }
+            continued = line
+                .ends_with('\\')
+                .then(|| continued.unwrap_or(line.starts_with('\t')));
+        }
+
+        let mut rules: Vec<MakeRule> = vec![];
+        let mut comments: Vec<&str> = vec![];
+        let mut default = None;
+        let mut in_rule = false;
+        for (number, line) in &lines {
+            let line_number = *number;
+            let number = Some(line_number);
+            if let Some(recipe) = line.strip_prefix('\t') {
+                // Recipe lines of a construct that couldn't be translated are skipped with it
+                if let Some(rule) = rules.last_mut().filter(|_| in_rule) {
+                    rule.body.push(recipe.to_string());
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                comments.clear();
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix('#') {
+                comments.push(comment.trim_start_matches('#').trim());
+                continue;
+            }
+            let description = (!comments.is_empty()).then(|| comments.join(" "));
+            comments.clear();
+            in_rule = false;
+            let first_word = line.split_whitespace().next().unwrap_or_default();
+            if MAKE_DIRECTIVES.contains(&first_word) {
+                self.warn(number, format!("Couldn't translate `{}`", line.trim()));
+                continue;
+            }
+            if let Some(goal) = line.strip_prefix(".DEFAULT_GOAL").and_then(|rest| {
+                rest.trim_start()
+                    .strip_prefix(":=")
+                    .or(rest.trim_start().strip_prefix('='))
+            }) {
+                default = Some(goal.trim().to_string());
+                continue;
+            }
+            if let Some(captures) = MAKE_VARIABLE_REGEX.captures(line) {
+                let (name, operator, value) = (&captures[1], &captures[2], &captures[3]);
+                if operator == "+=" || operator == "!=" {
+                    self.warn(
+                        number,
+                        format!("Couldn't translate the {} assignment of {}", operator, name),
+                    );
+                    continue;
+                }
+                let (value, issues) = make_expand(value.trim(), None, MakeContext::Value);
+                if issues.is_empty() {
+                    // Make expands undefined variables to nothing, braise requires them
+                    for (variable, _) in env_requirements(&value)
+                        .into_iter()
+                        .filter(|(_, default)| default.is_none())
+                    {
+                        if !self
+                            .settings
+                            .env
+                            .iter()
+                            .flatten()
+                            .any(|(key, _)| key == variable)
+                        {
+                            self.warn(
+                                number,
+                                format!(
+                                    "{} now requires the environment variable {}",
+                                    name, variable
+                                ),
+                            );
+                        }
+                    }
+                    // `?=` only sets variables missing from the environment, as a default does
+                    let value = if operator != "?=" {
+                        value
+                    } else if env_requirements(&value).is_empty() && !value.contains(")}") {
+                        format!("{{env({}:{})}}", name, value)
+                    } else {
+                        self.warn(
+                            number,
+                            format!("{} is now set even if it is in the environment", name),
+                        );
+                        value
+                    };
+                    self.set_env(name, value);
+                } else {
+                    self.warn(
+                        number,
+                        format!(
+                            "Couldn't translate {} in variable {}",
+                            issues.join(", "),
+                            name
+                        ),
+                    );
+                }
+                continue;
+            }
+            if let Some(captures) = MAKE_RULE_REGEX.captures(line) {
+                let (rest, comment) = captures[2].split_once('#').unwrap_or((&captures[2], ""));
+                let (prerequisites, inline) = rest.split_once(';').unwrap_or((rest, ""));
+                if prerequisites.contains('=') {
+                    self.warn(
+                        number,
+                        format!("Couldn't translate the target-specific variable `{}`", line),
+                    );
+                    continue;
+                }
+                let comment = comment.trim_start_matches('#').trim();
+                rules.push(MakeRule {
+                    targets: captures[1].split_whitespace().map(String::from).collect(),
+                    line: line_number,
+                    description: if comment.is_empty() {
+                        description
+                    } else {
+                        Some(comment.to_string())
+                    },
+                    prerequisites: prerequisites
+                        .split_whitespace()
+                        .filter(|prerequisite| *prerequisite != "|")
+                        .map(String::from)
+                        .collect(),
+                    body: if inline.trim().is_empty() {
+                        vec![]
+                    } else {
+                        vec![inline.trim().to_string()]
+                    },
+                });
+                in_rule = true;
+                continue;
+            }
+            self.warn(number, format!("Couldn't translate `{}`", line.trim()));
+        }
+
+        let targets = rules
+            .iter()
+            .flat_map(|rule| rule.targets.iter())
+            .filter(|target| !target.starts_with('.') && !target.contains('%'))
+            .cloned()
+            .collect::<Vec<_>>();
+        for rule in rules {
+            let line = Some(rule.line);
+            for target in &rule.targets {
+                // Special targets like `.PHONY` don't define anything to run
+                if target.starts_with('.') {
+                    continue;
+                }
+                if target.contains('%') {
+                    self.warn(
+                        line,
+                        format!("Couldn't translate the pattern rule {}", target),
+                    );
+                    continue;
+                }
+                let mut dependencies = vec![];
+                for prerequisite in &rule.prerequisites {
+                    if targets.contains(prerequisite) {
+                        dependencies.push(prerequisite.to_string());
+                    } else {
+                        self.warn(
+                            line,
+                            format!(
+                                "Couldn't translate the file prerequisite {} of {}",
+                                prerequisite, target
+                            ),
+                        );
+                    }
+                }
+                let mut commands = vec![];
+                for recipe in &rule.body {
+                    let recipe = recipe.trim_start();
+                    let recipe = recipe.trim_start_matches(['@', '+']);
+                    let recipe = if let Some(recipe) = recipe.strip_prefix('-') {
+                        self.warn(
+                            line,
+                            format!("Errors are no longer ignored in `{}`", recipe.trim()),
+                        );
+                        recipe
+                    } else {
+                        recipe
+                    };
+                    let (command, issues) = make_expand(recipe, Some(target), MakeContext::Recipe);
+                    if !issues.is_empty() {
+                        self.warn(
+                            line,
+                            format!("Couldn't translate {} in {}", issues.join(", "), target),
+                        );
+                    }
+                    commands.push(command);
+                }
+                let task = self.tasks.entry(target.to_string()).or_default();
+                if task.description.is_none() {
+                    task.description = rule.description.clone();
+                }
+                if !dependencies.is_empty() {
+                    task.dependencies
+                        .get_or_insert_with(Vec::new)
+                        .extend(dependencies);
+                }
+                if !commands.is_empty() {
+                    if task.command.is_some() {
+                        self.warn(line, format!("Replacing the earlier recipe of {}", target));
+                    }
+                    let command = self.join_recipe(line, target, &commands);
+                    let task = self.tasks.get_mut(target.as_str()).expect("just inserted");
+                    task.command = Some(command);
+                }
+            }
+        }
+        let empty = self
+            .tasks
+            .iter()
+            .filter(|(_, task)| task.command.is_none() && task.dependencies.is_none())
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        for name in &empty {
+            self.warn(
+                None,
+                format!("Skipping {}, it has no recipe or prerequisite", name),
+            );
+            self.tasks.shift_remove(name);
+        }
+        // Nothing is left to run for the prerequisites skipped
+        for task in self.tasks.values_mut() {
+            if let Some(dependencies) = &mut task.dependencies {
+                dependencies.retain(|dependency| !empty.contains(dependency));
+            }
+        }
+        // Make runs the first target when none is given
+        self.settings.default = default.or_else(|| self.tasks.keys().next().cloned());
+    }
+
+    fn just(&mut self, source: &str) {
+        let mut recipes: Vec<JustRecipe> = vec![];
+        let mut variables: Vec<String> = vec![];
+        let mut aliases: Vec<(String, String, usize)> = vec![];
+        let mut comments: Vec<&str> = vec![];
+        let mut attributes: Vec<(String, Option<String>)> = vec![];
+        let mut in_recipe = false;
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            if in_recipe && (line.trim().is_empty() || line.starts_with([' ', '\t'])) {
+                if !line.trim().is_empty() {
+                    let recipe = recipes.last_mut().expect("in a recipe");
+                    let indent = recipe.indent.get_or_insert_with(|| {
+                        line[..line.len() - line.trim_start().len()].to_string()
+                    });
+                    let body = line
+                        .strip_prefix(indent.as_str())
+                        .unwrap_or(line.trim_start());
+                    recipe.body.push(body.to_string());
+                }
+                continue;
+            }
+            in_recipe = false;
+            let line = line.trim_end();
+            if line.is_empty() {
+                comments.clear();
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix('#') {
+                if !comment.starts_with('!') {
+                    comments.push(comment.trim());
+                }
+                continue;
+            }
+            if let Some(inner) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                attributes.extend(JUST_ATTRIBUTE_REGEX.captures_iter(inner).map(|captures| {
+                    (
+                        captures[1].to_string(),
+                        captures
+                            .get(2)
+                            .or(captures.get(3))
+                            .map(|value| value.as_str().to_string()),
+                    )
+                }));
+                continue;
+            }
+            let description = (!comments.is_empty()).then(|| comments.join(" "));
+            comments.clear();
+            let recipe_attributes = std::mem::take(&mut attributes);
+            if let Some(captures) = JUST_SETTING_REGEX.captures(line) {
+                self.just_setting(&captures[1], captures.get(2).map(|v| v.as_str()), number);
+                continue;
+            }
+            if let Some(captures) = JUST_ALIAS_REGEX.captures(line) {
+                aliases.push((captures[1].to_string(), captures[2].to_string(), number));
+                continue;
+            }
+            if let Some(captures) = JUST_VARIABLE_REGEX.captures(line) {
+                let name = captures[1].to_string();
+                match unquote(&captures[2]) {
+                    Some(value) => self.set_env(&name, value.to_string()),
+                    None => self.warn(
+                        Some(number),
+                        format!("Couldn't translate the value of {}", name),
+                    ),
+                }
+                variables.push(name);
+                continue;
+            }
+            if let Some(captures) = JUST_RECIPE_REGEX.captures(line) {
+                let attribute = |name: &str| {
+                    recipe_attributes
+                        .iter()
+                        .find(|(attribute, _)| attribute == name)
+                };
+                for (name, _) in &recipe_attributes {
+                    if !["doc", "group", "private"].contains(&name.as_str()) {
+                        self.warn(
+                            Some(number),
+                            format!("Couldn't translate the [{}] attribute", name),
+                        );
+                    }
+                }
+                let name = captures[1].to_string();
+                let params = JUST_PARAM_REGEX
+                    .captures_iter(&captures[2])
+                    .map(|param| {
+                        (
+                            param[1].to_string(),
+                            param.get(2).map(|default| {
+                                default
+                                    .as_str()
+                                    .trim_matches(|c| c == '"' || c == '\'')
+                                    .to_string()
+                            }),
+                            param[0].starts_with(['+', '*']),
+                        )
+                    })
+                    .collect();
+                let rest = &line[captures.get(2).map_or(0, |params| params.end())..];
+                let dependencies = rest.trim_start().strip_prefix(':').unwrap_or_default();
+                recipes.push(JustRecipe {
+                    private: name.starts_with('_') || attribute("private").is_some(),
+                    name,
+                    line: number,
+                    description: attribute("doc")
+                        .and_then(|(_, doc)| doc.clone())
+                        .or(description),
+                    group: attribute("group").and_then(|(_, group)| group.clone()),
+                    params,
+                    dependencies: dependencies.to_string(),
+                    body: vec![],
+                    indent: None,
+                });
+                in_recipe = true;
+                continue;
+            }
+            self.warn(Some(number), format!("Couldn't translate `{}`", line));
+        }
+
+        for recipe in recipes {
+            let line = Some(recipe.line);
+            let (dependencies, after) = recipe
+                .dependencies
+                .split_once("&&")
+                .unwrap_or((&recipe.dependencies, ""));
+            if !after.trim().is_empty() {
+                self.warn(
+                    line,
+                    format!(
+                        "Couldn't translate the dependencies run after {}: {}",
+                        recipe.name,
+                        after.trim()
+                    ),
+                );
+            }
+            for call in JUST_CALL_REGEX.find_iter(dependencies) {
+                self.warn(
+                    line,
+                    format!("Couldn't translate the dependency {}", call.as_str()),
+                );
+            }
+            let dependencies = JUST_CALL_REGEX
+                .replace_all(dependencies, "")
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            let args = recipe
+                .params
+                .iter()
+                .filter(|(_, _, variadic)| !variadic)
+                .map(|(name, default, _)| BraiseArg {
+                    name: name.to_string(),
+                    description: None,
+                    default: default.clone(),
+                    choices: None,
+                })
+                .collect::<Vec<_>>();
+            let mut body = recipe.body.as_slice();
+            if let Some(shebang) = body.first().filter(|line| line.starts_with("#!")) {
+                self.warn(
+                    line,
+                    format!(
+                        "{} is run with the shell of braise instead of `{}`",
+                        recipe.name,
+                        shebang.trim_start_matches("#!")
+                    ),
+                );
+                body = &body[1..];
+            }
+            let mut issues = vec![];
+            let mut ignored = vec![];
+            let commands = body
+                .iter()
+                .map(|command| {
+                    let command = command.trim_start_matches('@');
+                    let command = if let Some(command) = command.strip_prefix('-') {
+                        ignored.push(command);
+                        command
+                    } else {
+                        command
+                    };
+                    JUST_INTERPOLATION_REGEX
+                        .replace_all(command, |captures: &regex::Captures| {
+                            let expression = &captures[1];
+                            if let Some(index) = args.iter().position(|arg| arg.name == expression)
+                            {
+                                format!("{{{}}}", index)
+                            } else if recipe
+                                .params
+                                .iter()
+                                .any(|(name, _, variadic)| *variadic && name == expression)
+                            {
+                                // Extra arguments are appended to the command
+                                String::new()
+                            } else if variables.iter().any(|variable| variable == expression) {
+                                format!("${}", expression)
+                            } else {
+                                issues.push(format!("`{}`", &captures[0]));
+                                captures[0].to_string()
+                            }
+                        })
+                        .trim_end()
+                        .to_string()
+                })
+                .collect::<Vec<_>>();
+            if !issues.is_empty() {
+                self.warn(
+                    line,
+                    format!(
+                        "Couldn't translate {} in {}",
+                        issues.join(", "),
+                        recipe.name
+                    ),
+                );
+            }
+            for command in ignored {
+                self.warn(
+                    line,
+                    format!("Errors are no longer ignored in `{}`", command),
+                );
+            }
+            let command =
+                (!commands.is_empty()).then(|| self.join_recipe(line, &recipe.name, &commands));
+            let task = BraiseTask {
+                command,
+                description: recipe.description,
+                dependencies: (!dependencies.is_empty()).then_some(dependencies),
+                group: recipe.group,
+                private: recipe.private.then_some(true),
+                args: (!args.is_empty()).then_some(args),
+                ..Default::default()
+            };
+            self.tasks.insert(recipe.name, task);
+        }
+        for (alias, name, line) in aliases {
+            match self.tasks.get_mut(&name) {
+                Some(task) => task.aliases.get_or_insert_with(Vec::new).push(alias),
+                None => self.warn(
+                    Some(line),
+                    format!("Alias {} refers to an unknown recipe {}", alias, name),
+                ),
+            }
+        }
+    }
+
+    fn just_setting(&mut self, name: &str, value: Option<&str>, line: usize) {
+        let enabled = value.is_none_or(|value| value.trim() == "true");
+        match name {
+            "shell" => {
+                let shell = value
+                    .map(|value| {
+                        QUOTED_REGEX
+                            .captures_iter(value)
+                            .filter_map(|captures| captures.get(1).or(captures.get(2)))
+                            .map(|part| part.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .unwrap_or_default();
+                if shell.is_empty() {
+                    self.warn(Some(line), "Couldn't translate the shell setting");
+                } else {
+                    self.settings.shell = Some(shell);
+                }
+            }
+            "dotenv-load" if enabled => {
+                self.settings.dotenv = Some(StringOrBool(either::Either::Right(true)));
+            }
+            "dotenv-filename" | "dotenv-path" => match value.and_then(unquote) {
+                Some(path) => {
+                    self.settings.dotenv =
+                        Some(StringOrBool(either::Either::Left(path.to_string())));
+                }
+                None => self.warn(
+                    Some(line),
+                    format!("Couldn't translate the {} setting", name),
+                ),
+            },
+            _ => self.warn(
+                Some(line),
+                format!("Couldn't translate the {} setting", name),
+            ),
+        }
+    }
+}
+
+/// The contents of a single string literal, ignoring a trailing comment
+fn unquote(value: &str) -> Option<&str> {
+    let value = value.trim();
+    let captures = QUOTED_REGEX.captures(value)?;
+    let rest = value[captures.get(0)?.end()..].trim_start();
+    (captures.get(0)?.start() == 0 && (rest.is_empty() || rest.starts_with('#')))
+        .then(|| {
+            captures
+                .get(1)
+                .or(captures.get(2))
+                .map(|value| value.as_str())
+        })
+        .flatten()
+}
+
+/// Translate the variable references of Make text, returning those that couldn't be
+fn make_expand(text: &str, target: Option<&str>, context: MakeContext) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut issues = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some((_, '$')) => out.push('$'),
+            Some((start, open @ ('(' | '{'))) => {
+                let close = if open == '(' { ')' } else { '}' };
+                let mut depth = 1;
+                let mut end = None;
+                for (index, c) in chars.by_ref() {
+                    if c == open {
+                        depth += 1;
+                    } else if c == close {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = Some(index);
+                            break;
+                        }
+                    }
+                }
+                let Some(end) = end else {
+                    issues.push(format!("`{}`", &text[start - 1..]));
+                    out.push_str(&text[start - 1..]);
+                    break;
+                };
+                let name = &text[start + 1..end];
+                let is_variable =
+                    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if name == "MAKE" {
+                    out.push_str("make");
+                } else if is_variable && context == MakeContext::Recipe {
+                    out.push_str(&format!("${}", name));
+                } else if is_variable {
+                    out.push_str(&format!("{{env({})}}", name));
+                } else {
+                    issues.push(format!("`{}`", &text[start - 1..=end]));
+                    out.push_str(&text[start - 1..=end]);
+                }
+            }
+            Some((_, '@')) if target.is_some() => out.push_str(target.unwrap_or_default()),
+            Some((_, c)) => {
+                issues.push(format!("`${}`", c));
+                out.push('$');
+                out.push(c);
+            }
+            None => out.push('$'),
+        }
+    }
+    (out, issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use indexmap::IndexMap;
+
+    use super::Converter;
+    use crate::{file::BraiseSettings, utils::replace_env_vars};
+
+    fn make(source: &str) -> Converter<'static> {
+        let mut converter = Converter {
+            path: "Makefile",
+            settings: BraiseSettings::default(),
+            tasks: IndexMap::new(),
+            warnings: vec![],
+        };
+        converter.make(source);
+        converter
+    }
+
+    fn command<'a>(converter: &'a Converter, task: &str) -> &'a str {
+        converter.tasks[task].command.as_deref().unwrap()
+    }
+
+    #[test]
+    fn make_chains_recipe_lines() {
+        let converter = make("build:\n\t@mkdir -p out\n\t$(CC) -o out/foo foo.c\n");
+        assert_eq!(
+            command(&converter, "build"),
+            "mkdir -p out && $CC -o out/foo foo.c"
+        );
+        assert!(converter.warnings.is_empty(), "{:?}", converter.warnings);
+    }
+
+    #[test]
+    fn make_merges_continued_recipe_lines() {
+        let converter = make(
+            "build:\n\t$(CC) -o foo \\\n\t\tfoo.c \\\nbar.c\n\techo done\n\nclean:\n\trm foo\n",
+        );
+        assert_eq!(
+            command(&converter, "build"),
+            "$CC -o foo \tfoo.c bar.c && echo done"
+        );
+        assert_eq!(command(&converter, "clean"), "rm foo");
+    }
+
+    #[test]
+    fn make_merges_continued_lines_outside_recipes() {
+        let converter = make("all: build \\\n  test\nbuild:\n\tcargo build\ntest:\n\tcargo test\n");
+        assert_eq!(
+            converter.tasks["all"].dependencies,
+            Some(vec!["build".to_string(), "test".to_string()])
+        );
+    }
+
+    #[test]
+    fn make_reads_rules() {
+        let converter = make(
+            ".PHONY: all build\n\
+             .DEFAULT_GOAL := build\n\
+             # Build everything\n\
+             all: build docs.md\n\
+             build: ## Compile the project\n\
+             \tcargo build\n\
+             %.o: %.c\n\
+             \tcc -c $<\n\
+             docs.md:\n",
+        );
+        let all = &converter.tasks["all"];
+        assert_eq!(all.description.as_deref(), Some("Build everything"));
+        assert_eq!(all.dependencies, Some(vec!["build".to_string()]));
+        assert_eq!(
+            converter.tasks["build"].description.as_deref(),
+            Some("Compile the project")
+        );
+        assert!(!converter.tasks.contains_key("docs.md"));
+        assert_eq!(converter.settings.default.as_deref(), Some("build"));
+        assert_eq!(
+            converter.warnings,
+            [
+                "Makefile:7: Couldn't translate the pattern rule %.o",
+                "Makefile: Skipping docs.md, it has no recipe or prerequisite",
+            ]
+        );
+    }
+
+    #[test]
+    fn make_warns_about_what_changes() {
+        let converter = make(
+            "ifdef DEBUG\n\
+             deploy:\n\
+             \tcd site\n\
+             \t-rm -r old\n\
+             \trsync . host:$(DIR)/$@\n",
+        );
+        assert_eq!(
+            command(&converter, "deploy"),
+            "cd site && rm -r old && rsync . host:$DIR/deploy"
+        );
+        assert_eq!(
+            converter.warnings,
+            [
+                "Makefile:1: Couldn't translate `ifdef DEBUG`",
+                "Makefile:2: Errors are no longer ignored in `rm -r old`",
+                "Makefile:2: A `cd` in deploy now applies to the lines after it",
+            ]
+        );
+    }
+
+    #[test]
+    fn make_turns_variables_into_env() {
+        let converter = make("OUT := out\nBIN = $(OUT)/app\nFLAGS += -O2\nall:\n\techo $(BIN)\n");
+        let env = converter.settings.env.as_ref().unwrap();
+        assert_eq!(env["OUT"], "out");
+        assert_eq!(env["BIN"], "{env(OUT)}/app");
+        assert!(!env.contains_key("FLAGS"));
+        assert_eq!(
+            converter.warnings,
+            ["Makefile:3: Couldn't translate the += assignment of FLAGS"]
+        );
+    }
+
+    #[test]
+    fn make_keeps_conditional_assignments_as_defaults() {
+        let converter = make("CC ?= gcc\nOUT = out\nBIN ?= $(OUT)/app\nall:\n\t$(CC) -o $(BIN)\n");
+        let env = converter.settings.env.as_ref().unwrap();
+        assert_eq!(env["CC"], "{env(CC:gcc)}");
+        assert_eq!(env["BIN"], "{env(OUT)}/app");
+        assert_eq!(
+            converter.warnings,
+            ["Makefile:3: BIN is now set even if it is in the environment"]
+        );
+        let with_cc = HashMap::from([("CC".to_string(), "clang".to_string())]);
+        assert_eq!(replace_env_vars(&env["CC"], &with_cc).unwrap(), "clang");
+        assert_eq!(
+            replace_env_vars(&env["CC"], &HashMap::new()).unwrap(),
+            "gcc"
+        );
+    }
+}
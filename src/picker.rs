@@ -0,0 +1,81 @@
+use std::io::IsTerminal;
+
+use color_eyre::eyre::Result;
+use dialoguer::{theme::ColorfulTheme, FuzzySelect, Input, Select};
+use log::{debug, trace};
+
+use crate::{error::BraiseError, file::BraiseFile};
+
+/// Whether we are attached to a terminal and can prompt the user
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Let the user fuzzy-pick a task, then prompt for its declared arguments.
+///
+/// Returns `None` if the user cancelled the selection.
+pub fn pick_task(file: &BraiseFile) -> Result<Option<(String, Vec<String>)>> {
+    trace!("pick_task: entering");
+    let tasks = file.task_infos();
+    if tasks.is_empty() {
+        trace!("pick_task: exiting with error");
+        return Err(BraiseError::NoTask.into());
+    }
+    let width = tasks.iter().map(|task| task.name.len()).max().unwrap_or(0);
+    let items = tasks
+        .iter()
+        .map(|task| match task.description {
+            Some(desc) => format!("{:width$}  {}", task.name, desc),
+            None => task.name.to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    let theme = ColorfulTheme::default();
+    let Some(index) = FuzzySelect::with_theme(&theme)
+        .with_prompt("Select a task")
+        .items(&items)
+        .default(0)
+        .interact_opt()?
+    else {
+        trace!("pick_task: cancelled");
+        return Ok(None);
+    };
+    let name = tasks[index].name.to_string();
+    debug!("Picked task: {}", name);
+
+    let mut args = vec![];
+    for arg in tasks[index].args {
+        let prompt = match arg.description {
+            Some(ref desc) => format!("{} ({})", arg.name, desc),
+            None => arg.name.to_string(),
+        };
+        let value = if let Some(ref choices) = arg.choices {
+            let default = arg
+                .default
+                .as_ref()
+                .and_then(|default| choices.iter().position(|choice| choice == default))
+                .unwrap_or(0);
+            let Some(choice) = Select::with_theme(&theme)
+                .with_prompt(prompt)
+                .items(choices)
+                .default(default)
+                .interact_opt()?
+            else {
+                trace!("pick_task: cancelled");
+                return Ok(None);
+            };
+            choices[choice].to_string()
+        } else {
+            let mut input = Input::<String>::with_theme(&theme).with_prompt(prompt);
+            if let Some(ref default) = arg.default {
+                input = input.default(default.to_string());
+            }
+            input.interact_text()?
+        };
+        args.push(value);
+    }
+    debug!("Picked args: {:#?}", args);
+
+    trace!("pick_task: exiting");
+    Ok(Some((name, args)))
+}
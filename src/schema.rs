@@ -0,0 +1,81 @@
+use log::trace;
+use schemars::{generate::SchemaSettings, json_schema, Schema};
+use serde_json::Value;
+
+use crate::{
+    constants::{schema_url, LEGACY_SETTINGS_KEYS, SETTINGS_TABLE, TASK_FIELD_ALIASES},
+    file::BraiseSettings,
+    task::{BraiseArg, BraiseTask},
+};
+
+/// Generate the JSON schema of a Braisefile from the types it is deserialized into
+pub fn file_schema() -> Schema {
+    trace!("file_schema: entering");
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let settings = generator.subschema_for::<BraiseSettings>();
+    let task = generator.subschema_for::<BraiseTask>();
+    generator.subschema_for::<BraiseArg>();
+    let mut definitions = generator.take_definitions(true);
+
+    // serde aliases aren't part of the generated schema, so we add them as properties of their own
+    for name in ["BraiseTask", "BraiseArg"] {
+        let Some(Value::Object(properties)) = definitions
+            .get_mut(name)
+            .and_then(|definition| definition.get_mut("properties"))
+        else {
+            continue;
+        };
+        for (field, aliases) in TASK_FIELD_ALIASES {
+            let Some(property) = properties.get(field).cloned() else {
+                continue;
+            };
+            for alias in aliases {
+                properties.insert(alias.to_string(), property.clone());
+            }
+        }
+    }
+
+    // The legacy flat settings, only read when there is no settings table, and otherwise tasks
+    let tasks = json_schema!({
+        "anyOf": [
+            task,
+            { "type": "array", "items": task }
+        ]
+    });
+    let legacy = definitions
+        .get("BraiseSettings")
+        .and_then(|definition| definition.get("properties"))
+        .and_then(Value::as_object)
+        .map(|properties| {
+            LEGACY_SETTINGS_KEYS
+                .iter()
+                .filter_map(|key| {
+                    let setting = properties.get(*key)?.clone();
+                    let schema = json_schema!({ "anyOf": [setting, tasks] });
+                    Some((key.to_string(), schema.to_value()))
+                })
+                .collect::<serde_json::Map<_, _>>()
+        })
+        .unwrap_or_default();
+    let mut properties = serde_json::Map::new();
+    // The schema of JSON and YAML files can be set in the file itself
+    properties.insert(
+        "$schema".to_string(),
+        json_schema!({ "type": "string" }).to_value(),
+    );
+    properties.insert(SETTINGS_TABLE.to_string(), settings.to_value());
+    properties.extend(legacy);
+
+    let schema = json_schema!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "$id": schema_url(),
+        "title": "Braisefile",
+        "description": "A file describing the tasks run by braise",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": tasks,
+        "definitions": definitions
+    });
+    trace!("file_schema: exiting");
+    schema
+}
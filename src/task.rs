@@ -1,15 +1,29 @@
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+    sync::Mutex,
+    thread,
+};
 
-use color_eyre::{eyre::bail, owo_colors::OwoColorize};
+use color_eyre::{
+    eyre::bail,
+    owo_colors::{AnsiColors, OwoColorize},
+};
 use either::Either;
+use indexmap::IndexMap;
 use log::{debug, trace};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Serialize};
 use terminal_size::{terminal_size, Width};
 
 use crate::{
     error::BraiseError,
     file::BraiseFile,
-    utils::{get_shell_command, replace_args, replace_env_vars},
+    utils::{get_shell_command, replace_args, replace_env_vars, split_shell, with_task_env},
+    workspace::{cargo_packages, find_binary, CargoOptions, CargoPackage, CargoTarget},
 };
 
 /// A struct representing a Braise task
@@ -18,13 +32,16 @@ use crate::{
 /// cmd = "echo Hello, World!"
 /// desc = "Prints 'Hello, World!'"
 /// ```
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct BraiseTask {
     /// The command to run
     #[serde(alias = "cmd")]
     pub command: Option<String>,
+    /// A description of the task
     #[serde(alias = "desc")]
     pub description: Option<String>,
+    /// Other names the task can be run with
+    pub aliases: Option<Vec<String>>,
     /// References to other tasks that need to be run before this one
     #[serde(alias = "deps", alias = "depends", alias = "depends_on")]
     pub dependencies: Option<Vec<String>>,
@@ -43,10 +60,96 @@ pub struct BraiseTask {
         alias = "platform"
     )]
     pub runs_on: Option<Vec<String>>,
+    /// A condition the task only runs if, e.g. `env.CI == 'true' && exists('Dockerfile')`
+    #[serde(alias = "if")]
+    pub when: Option<String>,
     /// Whether to confirm before running the task (string = message, bool = confirm with default message)
     pub confirm: Option<StringOrBool>,
-    /// Try to find the command from the current cargo workspace
+    /// Run the binary of the cargo workspace named like the task, its package or its directory
     pub workspace: Option<bool>,
+    /// The features enabled when running a workspace binary
+    pub features: Option<Vec<String>>,
+    /// Whether to enable every feature when running a workspace binary
+    #[serde(alias = "all-features")]
+    pub all_features: Option<bool>,
+    /// Whether to disable the default features when running a workspace binary
+    #[serde(alias = "no-default-features")]
+    pub no_default_features: Option<bool>,
+    /// The profile a workspace binary is built with
+    pub profile: Option<String>,
+    /// Whether to build a workspace binary in release mode, like `profile = "release"`
+    pub release: Option<bool>,
+    /// The target triple a workspace binary is built for
+    pub target: Option<String>,
+    /// Run the command once per package of the Cargo workspace, from its directory, with
+    /// `{package}` and `{package_dir}` replaced by its name and directory
+    pub foreach: Option<Foreach>,
+    /// The group the task is listed under
+    pub group: Option<String>,
+    /// Whether to hide the task from the list of tasks
+    pub hidden: Option<bool>,
+    /// Whether the task can only be run as a dependency of another task
+    pub private: Option<bool>,
+    /// The arguments the task accepts, in the order of `{0}`, `{1}`, etc.
+    pub args: Option<Vec<BraiseArg>>,
+    /// Environment variables set for the task, which can reference others with `{env(VAR)}`
+    pub env: Option<IndexMap<String, String>>,
+    /// The file the task was imported from, if it isn't defined in the Braisefile
+    #[serde(skip)]
+    pub origin: Option<String>,
+    /// The Cargo target run by the task, for the tasks synthesized from a Cargo project
+    #[serde(skip)]
+    pub cargo: Option<CargoTarget>,
+}
+
+impl BraiseTask {
+    /// Whether the task can run on the current OS
+    pub fn matches_os(&self) -> bool {
+        self.runs_on
+            .as_ref()
+            .map(|os| {
+                os.iter()
+                    .any(|os| os.to_lowercase() == std::env::consts::OS.to_lowercase())
+            })
+            .unwrap_or(true)
+    }
+
+    /// Whether the task should be left out of the list of tasks
+    pub fn is_hidden(&self, name: &str) -> bool {
+        self.hidden.unwrap_or(false) || self.is_private(name)
+    }
+
+    /// Whether the task can only be run as a dependency, either explicitly or by starting with an underscore
+    pub fn is_private(&self, name: &str) -> bool {
+        self.private.unwrap_or(name.starts_with('_'))
+    }
+}
+
+/// What a task can be run for each of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Foreach {
+    /// The packages of the Cargo workspace
+    Workspace,
+}
+
+/// A struct representing an argument declared by a task
+/// ```toml
+/// [deploy]
+/// cmd = "./deploy.sh {0}"
+/// args = [{ name = "env", choices = ["staging", "production"], default = "staging" }]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BraiseArg {
+    /// The name of the argument, used in help and listings
+    pub name: String,
+    /// A description of the argument
+    #[serde(alias = "desc")]
+    pub description: Option<String>,
+    /// The value used when the argument is not provided
+    pub default: Option<String>,
+    /// The values the argument is allowed to take
+    pub choices: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -57,6 +160,217 @@ pub struct StringOrBool(#[serde(with = "either::serde_untagged")] pub Either<Str
 #[serde(transparent)]
 pub struct BoolOrU8(#[serde(with = "either::serde_untagged")] pub Either<bool, u8>);
 
+impl JsonSchema for StringOrBool {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "StringOrBool".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [{ "type": "string" }, { "type": "boolean" }]
+        })
+    }
+}
+
+impl JsonSchema for BoolOrU8 {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "BoolOrU8".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [{ "type": "boolean" }, { "type": "integer", "minimum": 0 }]
+        })
+    }
+}
+
+/// Report a task skipped because its `when` condition doesn't hold, unless quiet
+pub fn report_skipped(quiet: u8, file: &BraiseFile, task_name: &str, condition: &str) {
+    let quiet = (quiet > 0)
+        || match &file.settings.quiet {
+            Some(q) => match q.0 {
+                Either::Left(q) => q,
+                Either::Right(q) => q > 0,
+            },
+            _ => false,
+        };
+    if !quiet {
+        println!(
+            "{} {} {}",
+            "Skipped".yellow(),
+            task_name.bold(),
+            format!("(when {})", condition).dimmed()
+        );
+    }
+}
+
+/// The colors the output of each package is prefixed with, in turn
+const PACKAGE_COLORS: [AnsiColors; 6] = [
+    AnsiColors::Cyan,
+    AnsiColors::Magenta,
+    AnsiColors::Yellow,
+    AnsiColors::Green,
+    AnsiColors::Blue,
+    AnsiColors::Red,
+];
+
+/// Replace `{package}` and `{package_dir}` in the command run for a package
+fn replace_package(command: &str, name: &str, dir: &Path) -> String {
+    command
+        .replace("{package}", name)
+        .replace("{package_dir}", &dir.display().to_string())
+}
+
+/// Run the command of a `foreach` task for a package, from its directory, prefixing its output
+fn run_for_package(
+    shell: &Command,
+    shell_args: &[&str],
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    package: &CargoPackage,
+    prefix: String,
+    output_quiet: bool,
+) -> std::io::Result<ExitStatus> {
+    // The command runs from the package's directory, so relative paths would point elsewhere
+    let dir = if package.dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        &package.dir
+    }
+    .canonicalize()?;
+    let to_run = replace_package(command, &package.name, &dir);
+    let mut command = Command::new(shell.get_program());
+    command
+        .args(shell_args)
+        .arg(to_run)
+        .current_dir(&dir)
+        .envs(env_vars);
+    debug!("Running command for {}: {:#?}", package.name, command);
+    if output_quiet {
+        command.stdout(Stdio::null()).stderr(Stdio::null());
+    } else {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let mut child = command.spawn()?;
+    // Both streams are read at once so that neither fills up and blocks the child
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    // Each line is written at once, so that the lines of other packages and of the other stream
+    // don't end up in the middle of it
+    let forward = |stream: &mut dyn Read, output: &mut dyn Write| {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            let _ = output.write_all(format!("{} {}\n", prefix, line).as_bytes());
+        }
+    };
+    thread::scope(|scope| {
+        if let Some(mut stdout) = stdout {
+            scope.spawn(move || forward(&mut stdout, &mut std::io::stdout()));
+        }
+        if let Some(mut stderr) = stderr {
+            scope.spawn(move || forward(&mut stderr, &mut std::io::stderr()));
+        }
+    });
+    child.wait()
+}
+
+/// Run `command` from the directory of every package, `jobs` packages at a time, with
+/// `{package}` and `{package_dir}` replaced, prefixing each line they print with the name of the
+/// package. Returns the packages it failed for once all of them are done
+fn run_foreach(
+    shell: &Command,
+    shell_args: &[&str],
+    command: &str,
+    env_vars: &HashMap<String, String>,
+    packages: &[CargoPackage],
+    jobs: usize,
+    (title_quiet, output_quiet): (bool, bool),
+) -> Result<(), Vec<String>> {
+    trace!("run_foreach: entering");
+    let width = packages
+        .iter()
+        .map(|package| package.name.len())
+        .max()
+        .unwrap_or(0);
+    let queue = Mutex::new(packages.iter().enumerate());
+    let results = thread::scope(|scope| {
+        let workers = (0..jobs.min(packages.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let Some((index, package)) = queue.lock().unwrap().next() else {
+                            break results;
+                        };
+                        let prefix = format!("[{:width$}]", package.name)
+                            .color(PACKAGE_COLORS[index % PACKAGE_COLORS.len()])
+                            .to_string();
+                        let result = run_for_package(
+                            shell,
+                            shell_args,
+                            command,
+                            env_vars,
+                            package,
+                            prefix,
+                            output_quiet,
+                        );
+                        results.push((index, result));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .filter_map(|worker| {
+                worker
+                    .join()
+                    .map_err(|e| debug!("Error joining thread: {:#?}", e))
+                    .ok()
+            })
+            .flatten()
+            .collect::<HashMap<_, _>>()
+    });
+    // The packages of a worker that panicked have no result and count as failed
+    let failed = packages
+        .iter()
+        .enumerate()
+        .filter(|(index, package)| match results.get(index) {
+            Some(Ok(status)) => !status.success(),
+            Some(Err(e)) => {
+                debug!("Couldn't run the command for {}: {:#?}", package.name, e);
+                true
+            }
+            None => true,
+        })
+        .map(|(_, package)| package.name.to_string())
+        .collect::<Vec<_>>();
+    if !title_quiet {
+        let summary = format!(
+            "{} of {} packages succeeded",
+            packages.len() - failed.len(),
+            packages.len()
+        );
+        if failed.is_empty() {
+            println!("{}", summary.dimmed());
+        } else {
+            println!("{}, failed: {}", summary.dimmed(), failed.join(", ").red());
+        }
+    }
+    trace!("run_foreach: exiting");
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(failed)
+    }
+}
+
 pub fn run_task(
     quiet: u8,
     task: &BraiseTask,
@@ -70,98 +384,81 @@ pub fn run_task(
 
     let use_workspace = task.workspace.unwrap_or(false);
 
-    if task.command.is_none() && !use_workspace {
+    if task.command.is_none() && !use_workspace && task.dependencies.is_none() {
         trace!("run_task: exiting with error");
         bail!(BraiseError::InvalidTask(
-            "Need either a command or workspace to run".to_string()
+            "Need either a command, dependencies or workspace to run".to_string()
         ));
     }
 
     if let Some(deps) = &task.dependencies {
         trace!("run_task: checking dependencies");
         for dep in deps {
-            if !file.tasks.contains_key(dep) {
+            let Some(dep) = file.resolve(dep) else {
                 trace!("run_task: exiting with error");
-                bail!(BraiseError::InvalidDependency(dep.to_string()));
-            }
-            if !ran.contains(dep) {
+                bail!(BraiseError::InvalidDependency(
+                    dep.to_string(),
+                    file.suggest(dep, true)
+                ));
+            };
+            if !ran.iter().any(|ran| ran == dep) {
                 debug!("Running dependency: {}", dep);
                 trace!("run_task: recursing");
-                let dep_tasks = file.tasks.get(dep).unwrap();
-                let dep_task = dep_tasks.iter().find(|task| {
-                    task.runs_on
-                        .as_ref()
-                        .map(|os| {
-                            os.iter()
-                                .any(|os| os.to_lowercase() == std::env::consts::OS.to_lowercase())
-                        })
-                        .unwrap_or(true)
-                });
-                if let Some(dep_task) = dep_task {
-                    run_task(
-                        quiet,
-                        &dep_task,
-                        args,
-                        file,
-                        env_vars,
-                        task_name,
-                        ran.clone(),
-                    )?;
-                    ran.push(dep.to_string());
-                } else {
-                    bail!(BraiseError::NoValidTask(dep.to_string()));
+                match file.pick_task(dep, env_vars)? {
+                    Ok(dep_task) => {
+                        run_task(quiet, dep_task, args, file, env_vars, dep, ran.clone())?;
+                    }
+                    Err(Some(condition)) => report_skipped(quiet, file, dep, condition),
+                    Err(None) => bail!(BraiseError::NoValidTask(dep.to_string())),
                 }
+                ran.push(dep.to_string());
             }
         }
     }
 
-    let resolved_command = if use_workspace {
-        let manifest = cargo_toml::Manifest::from_path("Cargo.toml")?;
-        if let Some(workspace) = manifest.workspace {
-            let members: Vec<String> = workspace
-                .members
-                .iter()
-                .map(|member| {
-                    member
-                        .split('/')
-                        .last()
-                        .unwrap_or(&member)
-                        .split('.')
-                        .next()
-                        .unwrap_or(&member)
-                        .to_string()
-                })
-                .collect();
-            let member = members.iter().find(|member| *member == task_name);
-            if let Some(member) = member {
-                format!("cargo run --bin {}", member)
-            } else {
-                bail!(BraiseError::NoValidTask(task_name.to_string()));
-            }
-        } else {
-            bail!(BraiseError::NoValidTask("No workspace found".to_string()));
-        }
-    } else {
-        task.command.clone().unwrap()
-    };
+    // Tasks without a command only group their dependencies
+    if task.command.is_none() && !use_workspace {
+        trace!("run_task: exiting");
+        return Ok(());
+    }
 
-    let (mut command, args) = replace_args(&resolved_command, args)?;
+    let env_vars = &with_task_env(env_vars, file, task)?;
 
-    command = replace_env_vars(&command, env_vars)?;
+    let target = if let Some(target) = &task.cargo {
+        Some(target.clone())
+    } else if use_workspace {
+        let packages = cargo_packages(file.dir())?;
+        let (package, bin) = find_binary(&packages, task_name)?;
+        Some(CargoTarget::bin(&package.name, bin))
+    } else {
+        None
+    };
+    // Cargo tasks run from the project they were found in
+    let dir = if target.is_some() {
+        file.dir().to_path_buf()
+    } else {
+        std::env::current_dir()?
+    };
+    // Cargo tasks take their arguments themselves, after the cargo options given with them, and
+    // aren't substituted so that `{0}` or `{env(VAR)}` in the arguments are passed as is
+    let (command, args) = match target {
+        Some(target) => {
+            let mut options = CargoOptions::from_task(task);
+            let args = options.take_flags(args);
+            (target.command(&options, &args), vec![])
+        }
+        None => {
+            let (command, args) = replace_args(task.command.as_ref().unwrap(), args)?;
+            (replace_env_vars(&command, env_vars)?, args)
+        }
+    };
 
     let shell_command = get_shell_command(task, file);
 
-    let (shell, shell_args) = if shell_command.contains(" ") {
-        let mut split = shell_command.split_whitespace();
-        let shell = split.next().unwrap();
-        let args = split.collect::<Vec<_>>();
-        (shell.to_string(), args)
-    } else {
-        (shell_command, vec![])
-    };
+    let (shell, shell_args) = split_shell(&shell_command);
     debug!("Using shell: {}", shell);
     debug!("Shell args: {:#?}", shell_args);
-    let mut shell = std::process::Command::new(shell);
+    let mut shell = Command::new(shell);
 
     let to_run = format!("{command} {}", args.join(" "));
 
@@ -173,9 +470,11 @@ pub fn run_task(
             },
             _ => false,
         }
-        || match file.quiet {
-            Either::Left(Some(q)) => q,
-            Either::Right(Some(q)) => q > 0,
+        || match &file.settings.quiet {
+            Some(q) => match q.0 {
+                Either::Left(q) => q,
+                Either::Right(q) => q > 0,
+            },
             _ => false,
         };
 
@@ -204,13 +503,6 @@ pub fn run_task(
         );
     }
 
-    let command = shell
-        .args(shell_args)
-        .arg(to_run)
-        .current_dir(std::env::current_dir()?)
-        .envs(env_vars);
-
-    debug!("Running command: {:#?}", command);
     let output_quiet = (quiet > 1)
         || match &task.quiet {
             Some(q) => match q.0 {
@@ -219,15 +511,58 @@ pub fn run_task(
             },
             _ => false,
         }
-        || match file.quiet {
-            Either::Left(Some(q)) => q,
-            Either::Right(Some(q)) => q > 1,
+        || match &file.settings.quiet {
+            Some(q) => match q.0 {
+                Either::Left(q) => q,
+                Either::Right(q) => q > 1,
+            },
             _ => false,
         };
+
+    if task.foreach.is_some() {
+        let mut packages = cargo_packages(file.dir())?;
+        if let Some(names) = &file.packages {
+            packages.retain(|package| names.contains(&package.name));
+        }
+        for package in &mut packages {
+            package.dir = file.dir().join(&package.dir);
+        }
+        let result = run_foreach(
+            &shell,
+            &shell_args,
+            &to_run,
+            env_vars,
+            &packages,
+            // Up to one package per CPU, or one at a time for projects where they contend for a
+            // shared resource like the target directory
+            match file.settings.parallel {
+                Some(false) => 1,
+                _ => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            },
+            (title_quiet, output_quiet),
+        );
+        trace!("run_task: exiting");
+        return result.map_err(|failed| {
+            BraiseError::Error(format!(
+                "Task {} failed for {}",
+                task_name,
+                failed.join(", ")
+            ))
+            .into()
+        });
+    }
+
+    let command = shell
+        .args(shell_args)
+        .arg(to_run)
+        .current_dir(dir)
+        .envs(env_vars);
+
+    debug!("Running command: {:#?}", command);
     if output_quiet {
         trace!("run_task: flushing stdout and stderr");
-        command.stdout(std::process::Stdio::null());
-        command.stderr(std::process::Stdio::null());
+        command.stdout(Stdio::null());
+        command.stderr(Stdio::null());
     }
 
     let mut child = command.spawn()?;
@@ -246,3 +581,22 @@ pub fn run_task(
     trace!("run_task: exiting");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::replace_package;
+
+    #[test]
+    fn replace_package_fills_in_every_placeholder() {
+        assert_eq!(
+            replace_package(
+                "echo {package} in {package_dir}; ls {package_dir}/src",
+                "core",
+                Path::new("/work/crates/core"),
+            ),
+            "echo core in /work/crates/core; ls /work/crates/core/src"
+        );
+    }
+}
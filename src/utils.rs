@@ -130,6 +130,47 @@ pub fn replace_env_vars(input: &str, env_vars: &HashMap<String, String>) -> Resu
     Ok(replaced.to_string())
 }
 
+/// Add the `env` of the file, then of the task, to the environment of a task
+pub fn with_task_env(
+    env_vars: &HashMap<String, String>,
+    file: &BraiseFile,
+    task: &BraiseTask,
+) -> Result<HashMap<String, String>> {
+    trace!("with_task_env: entering");
+    let mut env_vars = env_vars.clone();
+    for (key, value) in file
+        .settings
+        .env
+        .iter()
+        .flatten()
+        .chain(task.env.iter().flatten())
+    {
+        let value = replace_env_vars(value, &env_vars)?;
+        debug!("Setting env var: {}={}", key, value);
+        env_vars.insert(key.to_string(), value);
+    }
+    trace!("with_task_env: exiting");
+    Ok(env_vars)
+}
+
+/// The environment variables referenced with `{env(VAR)}` in a command, with their default value
+pub fn env_requirements(input: &str) -> Vec<(&str, Option<&str>)> {
+    ENV_REPLACE_REGEX
+        .captures_iter(input)
+        .map(|capture| {
+            (
+                capture.get(1).unwrap().as_str(),
+                capture.get(2).map(|default| default.as_str()),
+            )
+        })
+        .fold(vec![], |mut acc, requirement| {
+            if !acc.contains(&requirement) {
+                acc.push(requirement);
+            }
+            acc
+        })
+}
+
 pub fn replace_args(input: &str, args: &[String]) -> Result<(String, Vec<String>)> {
     trace!("replace_args: entering");
     let arguments_replace_indexes = ARG_REPLACE_REGEX
@@ -165,7 +206,7 @@ pub fn replace_args(input: &str, args: &[String]) -> Result<(String, Vec<String>
     debug!("Command after replacement: {}", command);
     // Remove used arguments
     let args = args
-        .into_iter()
+        .iter()
         .enumerate()
         .filter(|(i, _)| !arguments_replace_indexes.contains(i))
         .map(|(_, arg)| arg.to_string())
@@ -175,15 +216,51 @@ pub fn replace_args(input: &str, args: &[String]) -> Result<(String, Vec<String>
     Ok((command, args))
 }
 
+/// Fill in the defaults of the task's declared arguments and check their choices
+pub fn resolve_args(task: &BraiseTask, args: &[String]) -> Result<Vec<String>> {
+    trace!("resolve_args: entering");
+    let mut args = args.to_vec();
+    for (index, declared) in task.args.iter().flatten().enumerate() {
+        if index >= args.len() {
+            match declared.default {
+                Some(ref default) => {
+                    debug!("Using default value for {}: {}", declared.name, default);
+                    args.push(default.to_string());
+                }
+                None => break,
+            }
+        }
+        if let Some(ref choices) = declared.choices {
+            if !choices.contains(&args[index]) {
+                trace!("resolve_args: exiting with error");
+                bail!(BraiseError::InvalidArgChoice(
+                    declared.name.to_string(),
+                    args[index].to_string(),
+                    choices.join(", ")
+                ));
+            }
+        }
+    }
+    trace!("resolve_args: exiting");
+    Ok(args)
+}
+
+/// Split a shell command like `sh -c` into the program and its arguments
+pub fn split_shell(shell_command: &str) -> (&str, Vec<&str>) {
+    let mut split = shell_command.split_whitespace();
+    let shell = split.next().unwrap_or(shell_command);
+    (shell, split.collect())
+}
+
 pub fn get_shell_command(task: &BraiseTask, file: &BraiseFile) -> String {
     trace!("get_shell_command: entering");
     if let Some(ref shell) = task.shell {
         debug!("Using task shell: {}", shell);
         shell.to_string()
-    } else if let Some(ref shell) = file.shell {
+    } else if let Some(ref shell) = file.settings.shell {
         debug!("Using file shell: {}", shell);
         shell.to_string()
-    } else if let Some(shell) = std::env::var("SHELL").ok() {
+    } else if let Ok(shell) = std::env::var("SHELL") {
         debug!("Using SHELL env var: {}", shell);
         match shell.as_str() {
             "powershell" => format!("{} -Command", shell),
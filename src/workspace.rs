@@ -0,0 +1,444 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{bail, Context, Result};
+use log::{debug, trace};
+
+use crate::{error::BraiseError, task::BraiseTask};
+
+/// The group the tasks synthesized from a Cargo project are listed under
+pub const CARGO_GROUP: &str = "cargo";
+
+/// What a Cargo task runs, e.g. `cargo run -p server --bin server`
+#[derive(Debug, Clone)]
+pub struct CargoTarget {
+    /// The cargo subcommand, `run`, `test` or `bench`
+    pub subcommand: &'static str,
+    pub package: String,
+    /// The kind of target (`bin`, `example` or `bench`) and its name, all of them if `None`
+    pub target: Option<(&'static str, String)>,
+}
+
+impl CargoTarget {
+    pub fn bin(package: &str, bin: &str) -> Self {
+        Self {
+            subcommand: "run",
+            package: package.to_string(),
+            target: Some(("bin", bin.to_string())),
+        }
+    }
+
+    /// The cargo command running the target with `options`. The arguments go to the program run
+    /// by `cargo run`, and to `cargo test` and `cargo bench` otherwise, those following a `--`
+    /// going to the test harness
+    pub fn command(&self, options: &CargoOptions, args: &[String]) -> String {
+        let mut command = vec![
+            "cargo".to_string(),
+            self.subcommand.to_string(),
+            "-p".to_string(),
+            self.package.to_string(),
+        ];
+        if let Some((kind, name)) = &self.target {
+            command.push(format!("--{} {}", kind, name));
+        }
+        command.extend(options.flags());
+        let (args, after) = match args.iter().position(|arg| arg == "--") {
+            Some(index) => (&args[..index], Some(&args[index + 1..])),
+            None => (args, None),
+        };
+        if self.subcommand == "run" {
+            if !args.is_empty() || after.is_some_and(|after| !after.is_empty()) {
+                command.push("--".to_string());
+            }
+            command.extend(args.iter().chain(after.into_iter().flatten()).cloned());
+        } else {
+            command.extend(args.iter().cloned());
+            if let Some(after) = after {
+                command.push("--".to_string());
+                command.extend(after.iter().cloned());
+            }
+        }
+        command.join(" ")
+    }
+}
+
+/// The options of the cargo command run by a task, from its fields and the command line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CargoOptions {
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub profile: Option<String>,
+    pub target: Option<String>,
+}
+
+impl CargoOptions {
+    pub fn from_task(task: &BraiseTask) -> Self {
+        Self {
+            features: task.features.clone().unwrap_or_default(),
+            all_features: task.all_features.unwrap_or(false),
+            no_default_features: task.no_default_features.unwrap_or(false),
+            profile: task
+                .profile
+                .clone()
+                .or(task.release.unwrap_or(false).then(|| "release".to_string())),
+            target: task.target.clone(),
+        }
+    }
+
+    /// Take the cargo options given before any `--` out of `args`, overriding those of the task,
+    /// and return the other arguments
+    pub fn take_flags(&mut self, args: &[String]) -> Vec<String> {
+        let mut rest = vec![];
+        let mut args = args.iter().peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--" => {
+                    rest.push(arg.to_string());
+                    rest.extend(args.cloned());
+                    break;
+                }
+                "--release" => self.profile = Some("release".to_string()),
+                "--all-features" => self.all_features = true,
+                "--no-default-features" => self.no_default_features = true,
+                // The `--` isn't the value of the flag, the arguments after it are the program's
+                "--features" | "-F" | "--profile" | "--target" => {
+                    match args.next_if(|value| *value != "--") {
+                        Some(value) => self.set(arg, value),
+                        None => rest.push(arg.to_string()),
+                    }
+                }
+                _ => match arg.split_once('=') {
+                    Some((flag, value))
+                        if ["--features", "--profile", "--target"].contains(&flag) =>
+                    {
+                        self.set(flag, value)
+                    }
+                    _ => rest.push(arg.to_string()),
+                },
+            }
+        }
+        debug!("Cargo options: {:?}", self);
+        rest
+    }
+
+    fn set(&mut self, flag: &str, value: &str) {
+        match flag {
+            "--profile" => self.profile = Some(value.to_string()),
+            "--target" => self.target = Some(value.to_string()),
+            _ => self.features.extend(
+                value
+                    .split([',', ' '])
+                    .filter(|feature| !feature.is_empty())
+                    .map(|feature| feature.to_string()),
+            ),
+        }
+    }
+
+    /// The flags passed to cargo
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags = vec![];
+        if !self.features.is_empty() {
+            flags.push(format!("--features {}", self.features.join(",")));
+        }
+        if self.all_features {
+            flags.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            flags.push("--no-default-features".to_string());
+        }
+        match self.profile.as_deref() {
+            Some("release") => flags.push("--release".to_string()),
+            Some(profile) => flags.push(format!("--profile {}", profile)),
+            None => {}
+        }
+        if let Some(target) = &self.target {
+            flags.push(format!("--target {}", target));
+        }
+        flags
+    }
+}
+
+/// A package of a Cargo project, with the targets it builds
+#[derive(Debug, Clone)]
+pub struct CargoPackage {
+    pub name: String,
+    /// The directory of the package, relative to the project's root
+    pub dir: PathBuf,
+    /// The binaries of the package, declared with `[[bin]]` or found in `src/`
+    pub bins: Vec<String>,
+    /// The examples of the package, declared with `[[example]]` or found in `examples/`
+    pub examples: Vec<String>,
+    /// The benchmarks of the package, declared with `[[bench]]` or found in `benches/`
+    pub benches: Vec<String>,
+    /// The names of the packages it depends on, including development, build and
+    /// platform-specific dependencies
+    pub dependencies: Vec<String>,
+}
+
+impl CargoPackage {
+    fn from_path(root: &Path, dir: &Path) -> Result<Option<Self>> {
+        let path = root.join(dir).join("Cargo.toml");
+        let manifest = cargo_toml::Manifest::from_path(&path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let Some(package) = manifest.package else {
+            return Ok(None);
+        };
+        // Renamed dependencies are known by their package name
+        let dependencies = [
+            &manifest.dependencies,
+            &manifest.dev_dependencies,
+            &manifest.build_dependencies,
+        ]
+        .into_iter()
+        .chain(manifest.target.values().flat_map(|target| {
+            [
+                &target.dependencies,
+                &target.dev_dependencies,
+                &target.build_dependencies,
+            ]
+        }))
+        .flat_map(|deps| {
+            deps.iter()
+                .map(|(name, dep)| dep.package().unwrap_or(name).to_string())
+        })
+        .fold(vec![], |mut acc, name| {
+            if !acc.contains(&name) {
+                acc.push(name);
+            }
+            acc
+        });
+        Ok(Some(Self {
+            name: package.name,
+            dir: dir.to_path_buf(),
+            bins: manifest
+                .bin
+                .into_iter()
+                .filter_map(|bin| bin.name)
+                .collect(),
+            examples: manifest
+                .example
+                .into_iter()
+                .filter_map(|example| example.name)
+                .collect(),
+            benches: manifest
+                .bench
+                .into_iter()
+                .filter_map(|bench| bench.name)
+                .collect(),
+            dependencies,
+        }))
+    }
+}
+
+/// The packages of the Cargo project in `root`: the package at its root if any, then the members
+/// of its workspace, with globs like `crates/*` expanded
+pub fn cargo_packages(root: &Path) -> Result<Vec<CargoPackage>> {
+    trace!("cargo_packages: entering");
+    // The targets of a package are found by listing its directory, which can't be empty
+    let root = if root.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        root
+    };
+    let path = root.join("Cargo.toml");
+    let manifest = cargo_toml::Manifest::from_path(&path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    let mut packages = vec![];
+    if manifest.package.is_some() {
+        packages.extend(CargoPackage::from_path(root, Path::new(""))?);
+    }
+    let Some(workspace) = manifest.workspace else {
+        trace!("cargo_packages: exiting");
+        return Ok(packages);
+    };
+    let expand = |patterns: &[String]| -> Result<Vec<PathBuf>> {
+        let mut dirs = vec![];
+        for pattern in patterns {
+            let pattern = root.join(pattern);
+            for dir in glob::glob(&pattern.to_string_lossy())
+                .with_context(|| format!("Invalid workspace member {}", pattern.display()))?
+                .flatten()
+            {
+                let dir = dir.strip_prefix(root).unwrap_or(&dir).to_path_buf();
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+        Ok(dirs)
+    };
+    let excluded = expand(&workspace.exclude)?;
+    for dir in expand(&workspace.members)? {
+        // Globs also match files and directories that aren't packages
+        if excluded.contains(&dir) || !root.join(&dir).join("Cargo.toml").is_file() {
+            debug!("Skipping workspace member {}", dir.display());
+            continue;
+        }
+        // The root package can also be listed as a member
+        if let Some(package) = CargoPackage::from_path(root, &dir)? {
+            if !packages.iter().any(|other| other.name == package.name) {
+                packages.push(package);
+            }
+        }
+    }
+    debug!("Found {} packages", packages.len());
+    trace!("cargo_packages: exiting");
+    Ok(packages)
+}
+
+/// Find the binary run by a `workspace = true` task, named after the binary itself, its package
+/// or the directory of its package
+pub fn find_binary<'a>(
+    packages: &'a [CargoPackage],
+    name: &str,
+) -> Result<(&'a CargoPackage, &'a str)> {
+    trace!("find_binary: entering");
+    if let Some((package, bin)) = packages.iter().find_map(|package| {
+        package
+            .bins
+            .iter()
+            .find(|bin| *bin == name)
+            .map(|bin| (package, bin.as_str()))
+    }) {
+        trace!("find_binary: exiting");
+        return Ok((package, bin));
+    }
+    let Some(package) = packages.iter().find(|package| {
+        package.name == name || package.dir.file_name().is_some_and(|dir| dir == name)
+    }) else {
+        trace!("find_binary: exiting with error");
+        bail!(BraiseError::NoValidTask(name.to_string()));
+    };
+    match package.bins.as_slice() {
+        [bin] => {
+            trace!("find_binary: exiting");
+            Ok((package, bin))
+        }
+        [] => {
+            trace!("find_binary: exiting with error");
+            bail!(BraiseError::NoBinary(package.name.to_string()))
+        }
+        bins => {
+            trace!("find_binary: exiting with error");
+            bail!(BraiseError::AmbiguousBinary(
+                package.name.to_string(),
+                bins.join(", ")
+            ))
+        }
+    }
+}
+
+/// Whether a task is named like those synthesized from a Cargo project
+pub fn is_cargo_task(name: &str) -> bool {
+    ["bin:", "example:", "bench:", "test:"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Synthesize a task for every binary (`bin:NAME`), example (`example:NAME`) and benchmark
+/// (`bench:NAME`) of the Cargo project in `root`, and one running the tests of each of its
+/// packages (`test:PACKAGE`)
+pub fn cargo_tasks(root: &Path) -> Result<Vec<(String, BraiseTask)>> {
+    trace!("cargo_tasks: entering");
+    let origin = root.join("Cargo.toml").display().to_string();
+    let mut tasks = vec![];
+    for package in cargo_packages(root)? {
+        let targets = [
+            ("bin", "run", &package.bins, "Run the binary"),
+            ("example", "run", &package.examples, "Run the example"),
+            ("bench", "bench", &package.benches, "Run the benchmark"),
+        ];
+        for (kind, subcommand, names, description) in targets {
+            for name in names {
+                let target = CargoTarget {
+                    subcommand,
+                    package: package.name.to_string(),
+                    target: Some((kind, name.to_string())),
+                };
+                tasks.push((
+                    format!("{}:{}", kind, name),
+                    format!("{} {} of {}", description, name, package.name),
+                    target,
+                ));
+            }
+        }
+        tasks.push((
+            format!("test:{}", package.name),
+            format!("Run the tests of {}", package.name),
+            CargoTarget {
+                subcommand: "test",
+                package: package.name.to_string(),
+                target: None,
+            },
+        ));
+    }
+    let tasks = tasks
+        .into_iter()
+        .map(|(name, description, target)| {
+            let task = BraiseTask {
+                command: Some(target.command(&CargoOptions::default(), &[])),
+                description: Some(description),
+                group: Some(CARGO_GROUP.to_string()),
+                origin: Some(origin.to_string()),
+                cargo: Some(target),
+                ..Default::default()
+            };
+            (name, task)
+        })
+        .collect::<Vec<_>>();
+    debug!("Synthesized {} Cargo tasks", tasks.len());
+    trace!("cargo_tasks: exiting");
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CargoOptions, CargoTarget};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn take_flags_overrides_the_task_options() {
+        let mut options = CargoOptions {
+            features: vec!["tls".to_string()],
+            target: Some("x86_64-unknown-linux-gnu".to_string()),
+            ..Default::default()
+        };
+        let rest = options.take_flags(&args(&[
+            "--release",
+            "-F",
+            "metrics,log",
+            "--target=wasm32-unknown-unknown",
+            "--port",
+            "8080",
+        ]));
+        assert_eq!(rest, args(&["--port", "8080"]));
+        assert_eq!(options.features, ["tls", "metrics", "log"]);
+        assert_eq!(options.profile.as_deref(), Some("release"));
+        assert_eq!(options.target.as_deref(), Some("wasm32-unknown-unknown"));
+    }
+
+    #[test]
+    fn take_flags_stops_at_the_separator() {
+        let mut options = CargoOptions::default();
+        let rest = options.take_flags(&args(&["--features", "--", "--target", "x", "--release"]));
+        assert_eq!(
+            rest,
+            args(&["--features", "--", "--target", "x", "--release"])
+        );
+        assert_eq!(options, CargoOptions::default());
+    }
+
+    #[test]
+    fn command_passes_the_arguments_to_the_binary() {
+        let target = CargoTarget::bin("server", "srv");
+        let mut options = CargoOptions::default();
+        let rest = options.take_flags(&args(&["--release", "{0}", "--", "--target", "x"]));
+        assert_eq!(
+            target.command(&options, &rest),
+            "cargo run -p server --bin srv --release -- {0} --target x"
+        );
+    }
+}